@@ -0,0 +1,86 @@
+use std::hint::black_box;
+use std::time::{ Duration, Instant };
+
+use chroma::{ Layout, Section, SectionBuilder };
+use glam::IVec3;
+
+const RUNS: u32 = 20;
+
+// a 16x16x16 section holding `variety` distinct items
+fn section(layout: Layout, variety: u64) -> Section<u64, 16, 16, 16> {
+    let mut section: Section<u64, 16, 16, 16> = SectionBuilder::new().layout(layout).build();
+    let mut item: u64 = 0;
+
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                section.set_item(IVec3::new(x, y, z), item).unwrap();
+                item = (item + 1) % variety;
+            }
+        }
+    }
+
+    section
+}
+
+fn read_all(section: &Section<u64, 16, 16, 16>) -> u64 {
+    let mut sum: u64 = 0;
+
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                sum = sum.wrapping_add(*section.item(IVec3::new(x, y, z)).unwrap());
+            }
+        }
+    }
+
+    sum
+}
+
+fn write_all(section: &mut Section<u64, 16, 16, 16>, variety: u64) {
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                let item: u64 = ((x * 7 + y * 3 + z) as u64) % variety;
+                section.set_item(IVec3::new(x, y, z), item).unwrap();
+            }
+        }
+    }
+}
+
+fn time(mut f: impl FnMut()) -> f64 {
+    let mut total: Duration = Duration::ZERO;
+
+    for _ in 0..RUNS {
+        let start: Instant = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+
+    ((total / RUNS).as_nanos() as f64) / 4096.0
+}
+
+fn main() {
+    println!("16x16x16 section, ns per item");
+
+    // 5, 7 and 12 bit indices straddle words in the spanning layout
+    for variety in [4, 32, 128, 4096] {
+        for layout in [Layout::Spanning, Layout::Aligned] {
+            let mut section: Section<u64, 16, 16, 16> = section(layout, variety);
+            let bits_per_item: u8 = section.bits_per_item();
+
+            let read: f64 = time(|| {
+                black_box(read_all(black_box(&section)));
+            });
+            let write: f64 = time(|| {
+                write_all(black_box(&mut section), variety);
+            });
+
+            let layout: String = format!("{layout:?}");
+            let heap_size: usize = section.heap_size();
+            println!(
+                "{layout:>8} {bits_per_item:>2} bits: {read:.2} read, {write:.2} write, {heap_size} bytes"
+            );
+        }
+    }
+}
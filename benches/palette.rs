@@ -0,0 +1,56 @@
+use std::hint::black_box;
+use std::time::{ Duration, Instant };
+
+use chroma::{ GrowthPolicy, Section, SectionBuilder };
+use glam::IVec3;
+
+const RUNS: u32 = 20;
+
+// writes every cell of a 16x16x16 section, cycling through `variety` distinct items
+fn bulk_fill(variety: u64, growth_policy: GrowthPolicy) -> Section<u64, 16, 16, 16> {
+    let mut section: Section<u64, 16, 16, 16> = SectionBuilder::new()
+        .growth_policy(growth_policy)
+        .build();
+    let mut item: u64 = 0;
+
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                section.set_item(IVec3::new(x, y, z), item).unwrap();
+                item = (item + 1) % variety;
+            }
+        }
+    }
+
+    section
+}
+
+fn bench_bulk_fill(variety: u64, growth_policy: GrowthPolicy) {
+    let mut total: Duration = Duration::ZERO;
+
+    for _ in 0..RUNS {
+        let start: Instant = Instant::now();
+        black_box(bulk_fill(black_box(variety), growth_policy));
+        total += start.elapsed();
+    }
+
+    let per_fill: Duration = total / RUNS;
+    let per_write: f64 = (per_fill.as_nanos() as f64) / 4096.0;
+    println!("{variety:>5} distinct items: {per_fill:>10.2?} per fill, {per_write:.1} ns per write");
+}
+
+fn main() {
+    let growth_policies: [GrowthPolicy; 3] = [
+        GrowthPolicy::Increment,
+        GrowthPolicy::Double,
+        GrowthPolicy::ByteAligned,
+    ];
+
+    for growth_policy in growth_policies {
+        println!("bulk fill of a 16x16x16 section, {growth_policy:?} growth");
+
+        for variety in [2, 16, 64, 256, 1024, 4096] {
+            bench_bulk_fill(variety, growth_policy);
+        }
+    }
+}
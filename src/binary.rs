@@ -0,0 +1,703 @@
+//! Versioned binary format for a [`Section`].
+//!
+//! All integers are little endian. A section is written as:
+//!
+//! | Field            | Size        | Notes                                                 |
+//! | ---------------- | ----------- | ----------------------------------------------------- |
+//! | magic            | 4           | `b"CHRM"`                                             |
+//! | major version    | 1           | readers reject majors newer than their own            |
+//! | minor version    | 1           | newer minors only append header fields                |
+//! | header length    | 2           | bytes of header that follow                           |
+//! | width            | 4           |                                                       |
+//! | height           | 4           |                                                       |
+//! | depth            | 4           |                                                       |
+//! | storage          | 1           | 0 uniform, 1 packed, 2 direct, 3 run length (major 2) |
+//! | bits per item    | 1           | width of the palette indices, 0 for uniform or direct |
+//! | layout           | 1           | 0 spanning, 1 aligned                                 |
+//! | initial bits     | 1           | bits per item used when the section is promoted       |
+//! | min bits         | 1           |                                                       |
+//! | max bits         | 1           |                                                       |
+//! | compaction       | 1           | 0 manual, 1 when sparse                               |
+//! | direct threshold | 1           | 255 for none                                          |
+//!
+//! followed by the body for the storage:
+//!
+//! - uniform: the single item.
+//! - packed palette: the palette length as a `u32` and each item, trimmed to live entries,
+//!   then the word count as a `u32` and each packed `u64` word.
+//! - direct: every item in the same order as [`Section::iter`].
+//! - run length: the palette as for packed, then the run count as a `u32`
+//!   and each run as its palette index and length, both LEB128 varints.
+//!   Runs follow the same order as [`Section::iter`], so they are longest along z.
+//!
+//! [`Section::write_to`] picks run length storage whenever it is smaller than the packed words.
+//!
+//! Header fields a reader does not know are skipped using the header length,
+//! so any reader understands every minor version of its major version.
+//! Major version 2 only adds run length storage, so readers accept both,
+//! and sections are written as major version 1 unless their body is run length
+//! so older readers can still load them.
+//! The growth policy is not written and is read back as the default.
+
+use crate::{
+    CompactionPolicy,
+    GrowthPolicy,
+    Layout,
+    PaletteItem,
+    Section,
+    Storage,
+    ValidationError,
+};
+use crate::packed::PackedArray;
+use crate::shared::GlobalId;
+use std::io::{ self, Read, Write };
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Newest major version of the binary format this crate reads and writes.
+pub const FORMAT_MAJOR: u8 = 2;
+
+/// Oldest major version of the binary format this crate reads and writes.
+pub const MIN_FORMAT_MAJOR: u8 = 1;
+
+/// Minor version of the binary format written by this crate.
+pub const FORMAT_MINOR: u8 = 0;
+
+const MAGIC: [u8; 4] = *b"CHRM";
+const HEADER_LEN: u16 = 20;
+const NO_DIRECT_THRESHOLD: u8 = u8::MAX;
+
+const UNIFORM: u8 = 0;
+const PACKED: u8 = 1;
+const DIRECT: u8 = 2;
+const RUN_LENGTH: u8 = 3;
+
+// how a section's items are written after the header
+enum Body<'a, T> {
+    Uniform(&'a T),
+    Packed(Vec<&'a T>, PackedArray),
+    RunLength(Vec<&'a T>, u8, Vec<(u64, u64)>),
+    Direct(&'a [T]),
+}
+
+pub(crate) fn write_varint(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    while value >= 0x80 {
+        ((value as u8) | 0x80).write_item(writer)?;
+        value >>= 7;
+    }
+    (value as u8).write_item(writer)
+}
+
+pub(crate) fn read_varint(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value: u64 = 0;
+
+    for shift in (0..64).step_by(7) {
+        let byte: u8 = u8::read_item(reader)?;
+        if shift == 63 && byte > 1 {
+            break;
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint is longer than 64 bits"))
+}
+
+const fn varint_len(value: u64) -> usize {
+    let bits: u32 = u64::BITS - (value | 1).leading_zeros();
+    bits.div_ceil(7) as usize
+}
+
+/// Error reading a section from the binary format.
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("Failed to read the section: {0}")] Io(#[from] io::Error),
+    #[error("Data does not start with the section magic bytes.")] BadMagic,
+    #[error("Format version {major}.{minor} is not supported.")] UnsupportedVersion {
+        major: u8,
+        minor: u8,
+    },
+    #[error("Section is {found:?} but was read as {expected:?}.")] DimensionMismatch {
+        expected: [u32; 3],
+        found: [u32; 3],
+    },
+    #[error("Invalid header: {0}.")] InvalidHeader(&'static str),
+    #[error(transparent)] Invalid(#[from] ValidationError),
+}
+
+/// Items that can be written to and read from the binary format.
+///
+/// Implemented for the primitive integers, `bool`, `String` and [`GlobalId`].
+pub trait BinaryItem: Sized {
+    /// Writes the item.
+    fn write_item(&self, writer: &mut impl Write) -> io::Result<()>;
+
+    /// Reads an item written by [`BinaryItem::write_item`].
+    fn read_item(reader: &mut impl Read) -> io::Result<Self>;
+}
+
+macro_rules! impl_binary_item_int {
+    ($($int:ty),*) => {
+        $(
+            impl BinaryItem for $int {
+                fn write_item(&self, writer: &mut impl Write) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn read_item(reader: &mut impl Read) -> io::Result<Self> {
+                    let mut bytes: [u8; size_of::<$int>()] = [0; size_of::<$int>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$int>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_item_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl BinaryItem for bool {
+    fn write_item(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u8).write_item(writer)
+    }
+
+    fn read_item(reader: &mut impl Read) -> io::Result<Self> {
+        match u8::read_item(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "bool must be 0 or 1")),
+        }
+    }
+}
+
+impl BinaryItem for String {
+    fn write_item(&self, writer: &mut impl Write) -> io::Result<()> {
+        let len: u32 = u32::try_from(self.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "string is too long")
+        })?;
+        len.write_item(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn read_item(reader: &mut impl Read) -> io::Result<Self> {
+        let len: u32 = u32::read_item(reader)?;
+        let mut bytes: Vec<u8> = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+
+        if bytes.len() != (len as usize) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+impl BinaryItem for GlobalId {
+    fn write_item(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.get().write_item(writer)
+    }
+
+    fn read_item(reader: &mut impl Read) -> io::Result<Self> {
+        u32::read_item(reader).map(GlobalId::from_raw)
+    }
+}
+
+impl<T: PaletteItem + BinaryItem, const W: usize, const H: usize, const D: usize> Section<
+    T,
+    W,
+    H,
+    D
+> {
+    /// Writes the section in the latest version of the [binary format](crate::binary).
+    ///
+    /// Freed palette entries are left out, so the palette indices may be narrower than in memory.
+    /// Sections not written as run length are marked major version 1, for older readers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(2);
+    /// section.set_item(IVec3::new(1, 2, 3), 7).unwrap();
+    ///
+    /// let mut bytes: Vec<u8> = Vec::new();
+    /// section.write_to(&mut bytes).unwrap();
+    ///
+    /// let read: Section<u64, 16, 16, 16> = Section::read_from(&mut bytes.as_slice()).unwrap();
+    /// assert_eq!(*read.item(IVec3::new(1, 2, 3)).unwrap(), 7);
+    /// assert_eq!(read.count_of(&0), 4095);
+    /// ```
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.write_to_version(writer, FORMAT_MAJOR)
+    }
+
+    /// Writes the section in the given major version of the [binary format](crate::binary),
+    /// for readers that do not understand the latest one.
+    ///
+    /// Returns an error if the version is not between [`MIN_FORMAT_MAJOR`] and [`FORMAT_MAJOR`].
+    pub fn write_to_version(&self, writer: &mut impl Write, major: u8) -> io::Result<()> {
+        if !(MIN_FORMAT_MAJOR..=FORMAT_MAJOR).contains(&major) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported format version"));
+        }
+
+        let body: Body<'_, T> = match &*self.storage {
+            Storage::Uniform(item) => Body::Uniform(item),
+            Storage::Indirect { palette, .. } if palette.live_len() == 1 => {
+                Body::Uniform(palette.usage().next().expect("one entry is live").0)
+            }
+            Storage::Indirect { palette, data } => {
+                let (live, remap) = palette.live_remap();
+                let bits_per_item: u8 = Self::bits_needed(live.len()).max(self.min_bits_per_item);
+                let data: PackedArray = data.repacked(Self::VOLUME, bits_per_item, |index| {
+                    remap[index as usize] as u64
+                });
+
+                let runs: Vec<(u64, u64)> = Self::runs(&data);
+                let runs_len: usize = runs
+                    .iter()
+                    .map(|&(palette_index, len)| varint_len(palette_index) + varint_len(len))
+                    .sum();
+
+                if major >= 2 && runs_len < size_of_val(data.words()) {
+                    Body::RunLength(live, bits_per_item, runs)
+                } else {
+                    Body::Packed(live, data)
+                }
+            }
+            Storage::Direct(data) => Body::Direct(data),
+        };
+
+        // only run length storage needs a reader of major version 2
+        let major: u8 = if matches!(body, Body::RunLength(..)) { major } else { MIN_FORMAT_MAJOR };
+
+        let (storage, bits_per_item): (u8, u8) = match &body {
+            Body::Uniform(_) => (UNIFORM, 0),
+            Body::Packed(_, data) => (PACKED, data.bits_per_item()),
+            Body::RunLength(_, bits_per_item, _) => (RUN_LENGTH, *bits_per_item),
+            Body::Direct(_) => (DIRECT, 0),
+        };
+
+        writer.write_all(&MAGIC)?;
+        major.write_item(writer)?;
+        FORMAT_MINOR.write_item(writer)?;
+        HEADER_LEN.write_item(writer)?;
+
+        for len in [W, H, D] {
+            u32::try_from(len)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "section is too large"))?
+                .write_item(writer)?;
+        }
+        writer.write_all(
+            &[
+                storage,
+                bits_per_item,
+                self.layout as u8,
+                self.initial_bits_per_item,
+                self.min_bits_per_item,
+                self.max_bits_per_item,
+                self.compaction_policy as u8,
+                self.direct_threshold.unwrap_or(NO_DIRECT_THRESHOLD),
+            ]
+        )?;
+
+        match body {
+            Body::Uniform(item) => item.write_item(writer)?,
+            Body::Packed(live, data) => {
+                Self::write_palette(writer, &live)?;
+                (data.words().len() as u32).write_item(writer)?;
+                for word in data.words() {
+                    word.write_item(writer)?;
+                }
+            }
+            Body::RunLength(live, _, runs) => {
+                Self::write_palette(writer, &live)?;
+                (runs.len() as u32).write_item(writer)?;
+                for (palette_index, len) in runs {
+                    write_varint(writer, palette_index)?;
+                    write_varint(writer, len)?;
+                }
+            }
+            Body::Direct(data) => {
+                for item in data {
+                    item.write_item(writer)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a section written by [`Section::write_to`].
+    ///
+    /// Returns an error if the data is not a section, was written by an unsupported major version,
+    /// has different dimensions or breaks the section's invariants.
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let mut magic: [u8; 4] = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+
+        let major: u8 = u8::read_item(reader)?;
+        let minor: u8 = u8::read_item(reader)?;
+        if !(MIN_FORMAT_MAJOR..=FORMAT_MAJOR).contains(&major) {
+            return Err(FormatError::UnsupportedVersion { major, minor });
+        }
+
+        let header_len: u16 = u16::read_item(reader)?;
+        if header_len < HEADER_LEN {
+            return Err(FormatError::InvalidHeader("header is too short"));
+        }
+        let mut header: Vec<u8> = vec![0; header_len as usize];
+        reader.read_exact(&mut header)?;
+        let mut header: &[u8] = &header;
+
+        let found: [u32; 3] = [
+            u32::read_item(&mut header)?,
+            u32::read_item(&mut header)?,
+            u32::read_item(&mut header)?,
+        ];
+        let expected: [u32; 3] = [W as u32, H as u32, D as u32];
+        if found != expected {
+            return Err(FormatError::DimensionMismatch { expected, found });
+        }
+
+        let [
+            storage,
+            bits_per_item,
+            layout,
+            initial_bits_per_item,
+            min_bits_per_item,
+            max_bits_per_item,
+            compaction_policy,
+            direct_threshold,
+        ]: [u8; 8] = header[..8].try_into().expect("header length was checked");
+
+        let layout: Layout = match layout {
+            0 => Layout::Spanning,
+            1 => Layout::Aligned,
+            _ => {
+                return Err(FormatError::InvalidHeader("unknown layout"));
+            }
+        };
+        let compaction_policy: CompactionPolicy = match compaction_policy {
+            0 => CompactionPolicy::Manual,
+            1 => CompactionPolicy::WhenSparse,
+            _ => {
+                return Err(FormatError::InvalidHeader("unknown compaction policy"));
+            }
+        };
+
+        Self::check_settings(initial_bits_per_item, min_bits_per_item, max_bits_per_item)?;
+
+        let storage: Storage<T> = match storage {
+            UNIFORM => Storage::Uniform(T::read_item(reader)?),
+            PACKED => {
+                let entries: Vec<T> = Self::read_palette(reader)?;
+
+                // checked before reading so a corrupt count cannot allocate a huge buffer
+                let word_count: usize = u32::read_item(reader)? as usize;
+                if bits_per_item > 64 {
+                    return Err(ValidationError::BitsPerItemTooLarge(bits_per_item).into());
+                }
+                let expected: usize = PackedArray::data_len(Self::VOLUME, bits_per_item, layout);
+                if word_count != expected {
+                    return Err(ValidationError::DataLength { expected, found: word_count }.into());
+                }
+
+                let mut words: Vec<u64> = Vec::with_capacity(word_count);
+                for _ in 0..word_count {
+                    words.push(u64::read_item(reader)?);
+                }
+                Self::checked_indirect(entries, bits_per_item, layout, words)?
+            }
+            DIRECT => {
+                let mut data: Vec<T> = Vec::with_capacity(Self::VOLUME);
+                for _ in 0..Self::VOLUME {
+                    data.push(T::read_item(reader)?);
+                }
+                Storage::Direct(data)
+            }
+            RUN_LENGTH if major >= 2 => {
+                let entries: Vec<T> = Self::read_palette(reader)?;
+                if bits_per_item > 64 {
+                    return Err(ValidationError::BitsPerItemTooLarge(bits_per_item).into());
+                }
+
+                let mut data: PackedArray = PackedArray::new(Self::VOLUME, bits_per_item, layout);
+                let mut item_index: usize = 0;
+
+                for _ in 0..u32::read_item(reader)? {
+                    let palette_index: u64 = read_varint(reader)?;
+                    let len: u64 = read_varint(reader)?;
+                    let fits: bool = palette_index
+                        .checked_shr(bits_per_item as u32)
+                        .is_none_or(|high| high == 0);
+
+                    if palette_index >= (entries.len() as u64) || !fits {
+                        return Err(
+                            (ValidationError::PaletteIndexOutOfRange {
+                                item_index,
+                                palette_index,
+                                palette_len: entries.len(),
+                            }).into()
+                        );
+                    }
+                    if len > ((Self::VOLUME - item_index) as u64) {
+                        return Err(
+                            (ValidationError::ItemCount {
+                                expected: Self::VOLUME,
+                                found: item_index.saturating_add(len as usize),
+                            }).into()
+                        );
+                    }
+
+                    unsafe {
+                        data.fill_unchecked(item_index, len as usize, palette_index);
+                    }
+                    item_index += len as usize;
+                }
+
+                if item_index != Self::VOLUME {
+                    return Err(
+                        (ValidationError::ItemCount {
+                            expected: Self::VOLUME,
+                            found: item_index,
+                        }).into()
+                    );
+                }
+                Self::checked_indirect(entries, bits_per_item, layout, data.into_words())?
+            }
+            _ => {
+                return Err(FormatError::InvalidHeader("unknown storage"));
+            }
+        };
+
+        Ok(Self {
+            storage: Arc::new(storage),
+            initial_bits_per_item,
+            compaction_policy,
+            direct_threshold: (direct_threshold != NO_DIRECT_THRESHOLD).then_some(direct_threshold),
+            growth_policy: GrowthPolicy::default(),
+            min_bits_per_item,
+            max_bits_per_item,
+            layout,
+            changes: None,
+        })
+    }
+
+    // consecutive runs of equal palette indices, in item index order
+    fn runs(data: &PackedArray) -> Vec<(u64, u64)> {
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+
+        for palette_index in data.iter(Self::VOLUME) {
+            match runs.last_mut() {
+                Some((last, len)) if *last == palette_index => {
+                    *len += 1;
+                }
+                _ => runs.push((palette_index, 1)),
+            }
+        }
+
+        runs
+    }
+
+    fn write_palette(writer: &mut impl Write, live: &[&T]) -> io::Result<()> {
+        (live.len() as u32).write_item(writer)?;
+        for item in live {
+            item.write_item(writer)?;
+        }
+        Ok(())
+    }
+
+    fn read_palette(reader: &mut impl Read) -> io::Result<Vec<T>> {
+        let palette_len: usize = u32::read_item(reader)? as usize;
+        let mut entries: Vec<T> = Vec::with_capacity(palette_len.min(Self::VOLUME));
+        for _ in 0..palette_len {
+            entries.push(T::read_item(reader)?);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SectionBuilder;
+    use glam::IVec3;
+
+    fn round_trip<T: PaletteItem + BinaryItem, const W: usize, const H: usize, const D: usize>(
+        section: &Section<T, W, H, D>
+    ) -> Section<T, W, H, D> {
+        let mut bytes: Vec<u8> = Vec::new();
+        section.write_to(&mut bytes).unwrap();
+        Section::read_from(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let builders: [SectionBuilder; 3] = [
+            SectionBuilder::new().bits_per_item(3).min_bits_per_item(2),
+            SectionBuilder::new().layout(Layout::Aligned).max_bits_per_item(12),
+            SectionBuilder::new().direct_threshold(Some(2)),
+        ];
+
+        for builder in builders {
+            let mut section: Section<u64, 8, 8, 8> = builder.build();
+            let read: Section<u64, 8, 8, 8> = round_trip(&section);
+            assert!(read.is_uniform());
+            assert!(read.is_empty());
+
+            section.fill_with(|pos| ((pos.x * pos.y + pos.z) % 7) as u64);
+            let read: Section<u64, 8, 8, 8> = round_trip(&section);
+
+            assert!(read.values().eq(section.values()));
+            assert_eq!(read.is_direct(), section.is_direct());
+            assert_eq!(read.layout(), section.layout());
+            assert_eq!(read.min_bits_per_item, section.min_bits_per_item);
+            assert_eq!(read.max_bits_per_item, section.max_bits_per_item);
+            assert_eq!(read.direct_threshold, section.direct_threshold);
+            for item in 0..7 {
+                assert_eq!(read.count_of(&item), section.count_of(&item));
+            }
+        }
+    }
+
+    #[test]
+    fn test_palette_is_trimmed() {
+        let mut section: Section<String, 4, 4, 4> = Section::new(0);
+        for x in 0..4 {
+            section.set_item(IVec3::new(x, 0, 0), format!("block {x}")).unwrap();
+        }
+        for x in 1..4 {
+            section.set_item(IVec3::new(x, 0, 0), String::new()).unwrap();
+        }
+        assert_eq!(section.bits_per_item(), 3);
+
+        let mut read: Section<String, 4, 4, 4> = round_trip(&section);
+        assert_eq!(read.bits_per_item(), 1);
+        assert_eq!(read.palette_len(), 2);
+        assert_eq!(read.item(IVec3::new(0, 0, 0)).unwrap(), "block 0");
+
+        read.set_item(IVec3::new(0, 0, 0), String::new()).unwrap();
+        assert!(round_trip(&read).is_uniform());
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(1);
+        section.set_item(IVec3::new(1, 1, 1), 9).unwrap();
+        let mut bytes: Vec<u8> = Vec::new();
+        section.write_to(&mut bytes).unwrap();
+
+        // a newer minor version with an extra header field
+        let mut newer: Vec<u8> = bytes.clone();
+        newer[5] = FORMAT_MINOR + 1;
+        newer[6..8].copy_from_slice(&(HEADER_LEN + 3).to_le_bytes());
+        let header_end: usize = 8 + (HEADER_LEN as usize);
+        newer.splice(header_end..header_end, [1, 2, 3]);
+        let read: Section<u64, 4, 4, 4> = Section::read_from(&mut newer.as_slice()).unwrap();
+        assert_eq!(*read.item(IVec3::new(1, 1, 1)).unwrap(), 9);
+
+        let mut newer: Vec<u8> = bytes.clone();
+        newer[4] = FORMAT_MAJOR + 1;
+        let result = Section::<u64, 4, 4, 4>::read_from(&mut newer.as_slice());
+        assert!(matches!(result, Err(FormatError::UnsupportedVersion { .. })));
+
+        let result = Section::<u64, 4, 4, 4>::read_from(&mut &bytes[1..]);
+        assert!(matches!(result, Err(FormatError::BadMagic)));
+
+        let result = Section::<u64, 4, 4, 8>::read_from(&mut bytes.as_slice());
+        assert!(matches!(result, Err(FormatError::DimensionMismatch { .. })));
+
+        let result = Section::<u64, 4, 4, 4>::read_from(&mut &bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(FormatError::Io(_))));
+    }
+
+    #[test]
+    fn test_run_length() {
+        const STORAGE: usize = 4 + 1 + 1 + 2 + 12;
+
+        let mut section: Section<u64, 16, 16, 16> = Section::new(4);
+        section.fill_region(IVec3::ZERO, IVec3::new(15, 3, 15), 1).unwrap();
+        section.fill_region(IVec3::new(2, 4, 0), IVec3::new(5, 9, 15), 2).unwrap();
+        section.set_item(IVec3::new(9, 9, 9), 3).unwrap();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        section.write_to(&mut bytes).unwrap();
+        let mut packed: Vec<u8> = Vec::new();
+        section.write_to_version(&mut packed, 1).unwrap();
+
+        assert_eq!(bytes[STORAGE], RUN_LENGTH);
+        assert_eq!(bytes[4], 2);
+        assert_eq!(packed[STORAGE], PACKED);
+        assert_eq!(packed[4], 1);
+        assert!(bytes.len() * 4 < packed.len());
+
+        for bytes in [bytes, packed] {
+            let read: Section<u64, 16, 16, 16> = Section::read_from(&mut bytes.as_slice()).unwrap();
+            assert!(read.values().eq(section.values()));
+            assert_eq!(read.count_of(&2), section.count_of(&2));
+            assert_eq!(read.bits_per_item(), 2);
+        }
+
+        let mut noisy: Section<u64, 16, 16, 16> = Section::new(1);
+        noisy.fill_with(|pos| ((pos.x * 31 + pos.y * 17 + pos.z * 7) % 4) as u64);
+        let mut bytes: Vec<u8> = Vec::new();
+        noisy.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes[STORAGE], PACKED);
+        assert_eq!(bytes[4], 1);
+        assert!(round_trip(&noisy).values().eq(noisy.values()));
+    }
+
+    #[test]
+    fn test_corrupt_runs_are_rejected() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(1);
+        section.set_item(IVec3::new(0, 0, 1), 1).unwrap();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        section.write_to(&mut bytes).unwrap();
+
+        // the last run is the 62 items after the single 1
+        let last: usize = bytes.len() - 1;
+        assert_eq!(bytes[last], 62);
+
+        bytes[last] = 61;
+        let result = Section::<u64, 4, 4, 4>::read_from(&mut bytes.as_slice());
+        assert!(
+            matches!(
+                result,
+                Err(FormatError::Invalid(ValidationError::ItemCount { expected: 64, found: 63 }))
+            )
+        );
+
+        bytes[last] = 63;
+        let result = Section::<u64, 4, 4, 4>::read_from(&mut bytes.as_slice());
+        assert!(matches!(result, Err(FormatError::Invalid(ValidationError::ItemCount { .. }))));
+
+        bytes[last - 1] = 2;
+        bytes[last] = 62;
+        let result = Section::<u64, 4, 4, 4>::read_from(&mut bytes.as_slice());
+        assert!(
+            matches!(
+                result,
+                Err(FormatError::Invalid(ValidationError::PaletteIndexOutOfRange { .. }))
+            )
+        );
+    }
+
+    #[test]
+    fn test_varints() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut bytes: Vec<u8> = Vec::new();
+            write_varint(&mut bytes, value).unwrap();
+            assert_eq!(bytes.len(), varint_len(value));
+            assert_eq!(read_varint(&mut bytes.as_slice()).unwrap(), value);
+        }
+        assert!(read_varint(&mut [0xff; 11].as_slice()).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{ CompactionPolicy, Error, GrowthPolicy, Layout, PaletteItem, Section };
+
+/// Configures how a [`Section`] stores and grows its items before creating it.
+///
+/// # Examples
+///
+/// ```
+/// use glam::IVec3;
+/// use chroma::{ GrowthPolicy, Section, SectionBuilder };
+///
+/// let mut section: Section<u64, 16, 16, 16> = SectionBuilder::new()
+///     .bits_per_item(2)
+///     .growth_policy(GrowthPolicy::ByteAligned)
+///     .max_bits_per_item(16)
+///     .build();
+///
+/// for z in 0..5 {
+///     section.set_item(IVec3::new(0, 0, z), (z as u64) + 1).unwrap();
+/// }
+/// assert_eq!(section.bits_per_item(), 4);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SectionBuilder {
+    bits_per_item: u8,
+    min_bits_per_item: u8,
+    max_bits_per_item: u8,
+    growth_policy: GrowthPolicy,
+    compaction_policy: CompactionPolicy,
+    direct_threshold: Option<u8>,
+    layout: Layout,
+    track_changes: bool,
+}
+
+impl SectionBuilder {
+    /// Creates a builder with the same settings as [`Section::new`] with zero bits per item.
+    pub const fn new() -> Self {
+        Self {
+            bits_per_item: 0,
+            min_bits_per_item: 0,
+            max_bits_per_item: Self::max_bits_per_item_default(),
+            growth_policy: GrowthPolicy::Increment,
+            compaction_policy: CompactionPolicy::Manual,
+            direct_threshold: None,
+            layout: Layout::Spanning,
+            track_changes: false,
+        }
+    }
+
+    /// Sets the bits per item used once a second distinct item is stored.
+    pub const fn bits_per_item(mut self, bits_per_item: u8) -> Self {
+        self.bits_per_item = bits_per_item;
+        self
+    }
+
+    /// Sets the fewest bits per item a packed palette is stored with,
+    /// including after compaction.
+    pub const fn min_bits_per_item(mut self, min_bits_per_item: u8) -> Self {
+        self.min_bits_per_item = min_bits_per_item;
+        self
+    }
+
+    /// Sets the most bits per item the growth policy may choose.
+    pub const fn max_bits_per_item(mut self, max_bits_per_item: u8) -> Self {
+        self.max_bits_per_item = max_bits_per_item;
+        self
+    }
+
+    /// Sets how the bits per item grow once the palette is full.
+    pub const fn growth_policy(mut self, growth_policy: GrowthPolicy) -> Self {
+        self.growth_policy = growth_policy;
+        self
+    }
+
+    /// Sets when unused palette entries are reclaimed.
+    pub const fn compaction_policy(mut self, compaction_policy: CompactionPolicy) -> Self {
+        self.compaction_policy = compaction_policy;
+        self
+    }
+
+    /// Sets the bits per item above which the palette is dropped.
+    pub const fn direct_threshold(mut self, direct_threshold: Option<u8>) -> Self {
+        self.direct_threshold = direct_threshold;
+        self
+    }
+
+    /// Sets how packed items are laid out in memory.
+    pub const fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets if the section records which items change, see [`Section::set_track_changes`].
+    pub const fn track_changes(mut self, track_changes: bool) -> Self {
+        self.track_changes = track_changes;
+        self
+    }
+
+    /// Creates a section with every item set to its default value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the minimum bits per item is greater than the maximum,
+    /// or the bits per item are more than 64.
+    pub fn build<T: PaletteItem + Default, const W: usize, const H: usize, const D: usize>(
+        self
+    ) -> Section<T, W, H, D> {
+        self.build_filled(T::default())
+    }
+
+    /// Creates a section with every item set to `item`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the minimum bits per item is greater than the maximum,
+    /// or the bits per item are more than 64.
+    pub fn build_filled<T: PaletteItem, const W: usize, const H: usize, const D: usize>(
+        self,
+        item: T
+    ) -> Section<T, W, H, D> {
+        match self.try_build_filled(item) {
+            Ok(section) => section,
+            Err(err) => panic!("invalid section settings: {err}"),
+        }
+    }
+
+    /// Creates a section with every item set to its default value,
+    /// or returns an error if the settings are invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chroma::{ Error, Section, SectionBuilder, ValidationError };
+    ///
+    /// let result: Result<Section<u64, 16, 16, 16>, Error> = SectionBuilder::new()
+    ///     .bits_per_item(65)
+    ///     .try_build();
+    ///
+    /// let expected: ValidationError = ValidationError::BitsPerItemTooLarge(65);
+    /// assert!(matches!(result, Err(Error::Invalid(err)) if err == expected));
+    /// ```
+    pub fn try_build<T: PaletteItem + Default, const W: usize, const H: usize, const D: usize>(
+        self
+    ) -> Result<Section<T, W, H, D>, Error> {
+        self.try_build_filled(T::default())
+    }
+
+    /// Creates a section with every item set to `item`,
+    /// or returns an error if the settings are invalid.
+    pub fn try_build_filled<T: PaletteItem, const W: usize, const H: usize, const D: usize>(
+        self,
+        item: T
+    ) -> Result<Section<T, W, H, D>, Error> {
+        Section::<T, W, H, D>::check_settings(
+            self.bits_per_item,
+            self.min_bits_per_item,
+            self.max_bits_per_item
+        )?;
+
+        let mut section: Section<T, W, H, D> = Section::filled(item, self.bits_per_item);
+        section.min_bits_per_item = self.min_bits_per_item;
+        section.max_bits_per_item = self.max_bits_per_item;
+        section.growth_policy = self.growth_policy;
+        section.compaction_policy = self.compaction_policy;
+        section.direct_threshold = self.direct_threshold;
+        section.layout = self.layout;
+        section.set_track_changes(self.track_changes);
+        Ok(section)
+    }
+
+    pub(crate) const fn max_bits_per_item_default() -> u8 {
+        64
+    }
+}
+
+impl Default for SectionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,70 @@
+use crate::PaletteItem;
+use glam::IVec3;
+use std::collections::HashMap;
+
+/// An item that changed since changes were last taken from a [`Section`](crate::Section).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<T> {
+    pub pos: IVec3,
+    pub old: T,
+    pub new: T,
+}
+
+/// Records which items of a section changed, and which regions they fall in.
+#[derive(Clone)]
+pub(crate) struct ChangeTracker<T: PaletteItem> {
+    changes: HashMap<usize, (T, T)>,
+    dirty: Vec<u64>,
+    changed: bool,
+}
+
+impl<T: PaletteItem> ChangeTracker<T> {
+    pub(crate) fn new(region_count: usize) -> Self {
+        Self {
+            changes: HashMap::new(),
+            dirty: vec![0; region_count.div_ceil(64)],
+            changed: false,
+        }
+    }
+
+    /// Records an item changing from `old` to `new`,
+    /// keeping the value it had when changes were last taken.
+    pub(crate) fn record(&mut self, item_index: usize, region_index: usize, old: T, new: T) {
+        self.changed = true;
+        self.dirty[region_index / 64] |= 1 << (region_index % 64);
+
+        match self.changes.get_mut(&item_index) {
+            Some((first, _)) if *first == new => {
+                self.changes.remove(&item_index);
+            }
+            Some((_, last)) => *last = new,
+            None => {
+                self.changes.insert(item_index, (old, new));
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) const fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    #[inline]
+    pub(crate) fn is_region_dirty(&self, region_index: usize) -> bool {
+        self.dirty[region_index / 64] & (1 << (region_index % 64)) != 0
+    }
+
+    /// Returns every changed item index with its old and new value, ordered by index,
+    /// and clears the changed flag and dirty mask.
+    pub(crate) fn take(&mut self) -> Vec<(usize, T, T)> {
+        self.changed = false;
+        self.dirty.fill(0);
+
+        let mut changes: Vec<(usize, T, T)> = self.changes
+            .drain()
+            .map(|(item_index, (old, new))| (item_index, old, new))
+            .collect();
+        changes.sort_unstable_by_key(|&(item_index, _, _)| item_index);
+        changes
+    }
+}
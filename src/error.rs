@@ -0,0 +1,39 @@
+use crate::{ BoundsError, FormatError, ValidationError };
+use glam::IVec3;
+use std::collections::TryReserveError;
+use thiserror::Error;
+
+/// Error returned by fallible operations on a [`Section`](crate::Section).
+///
+/// The more specific errors convert into it, and are kept as its source.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Position is out of the section bounds.")] Bounds(#[from] BoundsError),
+    #[error("Section data or settings are invalid.")] Invalid(#[from] ValidationError),
+    #[error("Failed to read section data.")] Format(#[from] FormatError),
+    #[error("Expected a section of dimensions {expected} but found {found}.")] DimensionMismatch {
+        expected: IVec3,
+        found: IVec3,
+    },
+    #[error("Failed to allocate memory for the section.")] Allocation(#[from] TryReserveError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_sources_are_kept() {
+        let err: Error = BoundsError::OutOfBounds(IVec3::new(-1, 0, 0)).into();
+        let source = err.source().unwrap().downcast_ref::<BoundsError>();
+        assert!(matches!(source, Some(BoundsError::OutOfBounds(pos)) if pos.x == -1));
+
+        let err: Error = ValidationError::BitsPerItemTooLarge(70).into();
+        let source = err.source().unwrap().downcast_ref::<ValidationError>();
+        assert_eq!(source, Some(&ValidationError::BitsPerItemTooLarge(70)));
+
+        let err: Error = FormatError::BadMagic.into();
+        assert!(err.source().unwrap().is::<FormatError>());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::PaletteItem;
+use crate::packed::PackedIter;
+use crate::palette::Palette;
+use std::slice;
+
+/// Iterator over the items of a [`Section`](crate::Section), returned by
+/// [`Section::values`](crate::Section::values).
+///
+/// Packed items are decoded one word at a time rather than looked up per position.
+pub struct Values<'a, T: PaletteItem> {
+    inner: ValuesInner<'a, T>,
+}
+
+enum ValuesInner<'a, T: PaletteItem> {
+    Uniform(&'a T, usize),
+    Indirect(&'a Palette<T>, PackedIter<'a>),
+    Direct(slice::Iter<'a, T>),
+}
+
+impl<'a, T: PaletteItem> Values<'a, T> {
+    pub(crate) const fn uniform(item: &'a T, len: usize) -> Self {
+        Self { inner: ValuesInner::Uniform(item, len) }
+    }
+
+    pub(crate) const fn indirect(palette: &'a Palette<T>, indices: PackedIter<'a>) -> Self {
+        Self { inner: ValuesInner::Indirect(palette, indices) }
+    }
+
+    pub(crate) fn direct(items: &'a [T]) -> Self {
+        Self { inner: ValuesInner::Direct(items.iter()) }
+    }
+}
+
+impl<'a, T: PaletteItem> Iterator for Values<'a, T> {
+    type Item = &'a T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a T> {
+        match &mut self.inner {
+            ValuesInner::Uniform(item, remaining) => {
+                if *remaining == 0 {
+                    return None;
+                }
+                *remaining -= 1;
+                Some(item)
+            }
+            ValuesInner::Indirect(palette, indices) => {
+                let palette_index: usize = indices.next()? as usize;
+                Some(unsafe { palette.get_unchecked(palette_index) })
+            }
+            ValuesInner::Direct(items) => items.next(),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining: usize = match &self.inner {
+            ValuesInner::Uniform(_, remaining) => *remaining,
+            ValuesInner::Indirect(_, indices) => indices.len(),
+            ValuesInner::Direct(items) => items.len(),
+        };
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T: PaletteItem> ExactSizeIterator for Values<'_, T> {}
@@ -0,0 +1,423 @@
+use crate::{ BoundsError, PaletteItem, Section };
+use crate::palette::Palette;
+use glam::IVec3;
+
+/// One mutation, with items stored as indices into the journal's palette.
+enum Edit {
+    Item {
+        item_index: u32,
+        old: u32,
+        new: u32,
+    },
+    /// Box set to one item, with the items it held as runs of `(len, old)` in item index order.
+    Region {
+        min: IVec3,
+        max: IVec3,
+        old: Vec<(u32, u32)>,
+        new: u32,
+    },
+}
+
+/// Edits undone and redone together.
+#[derive(Default)]
+struct Transaction {
+    edits: Vec<Edit>,
+}
+
+/// Wraps a [`Section`] and records every edit made through it so they can be undone and redone.
+///
+/// Edits are grouped into transactions with [`Journal::begin`] and [`Journal::commit`],
+/// edits made outside of one are each their own transaction.
+/// Recorded items are interned in a reference counted palette owned by the journal,
+/// so each edit stores small indices rather than full values,
+/// and values are freed once no recorded edit refers to them.
+///
+/// # Examples
+///
+/// ```
+/// use glam::IVec3;
+/// use chroma::{ Journal, Section };
+///
+/// let mut journal: Journal<u64, 16, 16, 16> = Journal::new(Section::new(1));
+///
+/// journal.begin();
+/// journal.set_item(IVec3::new(1, 2, 3), 4).unwrap();
+/// journal.fill_region(IVec3::new(0, 0, 0), IVec3::new(3, 3, 3), 5).unwrap();
+/// journal.commit();
+/// assert_eq!(journal.section().count_of(&5), 64);
+///
+/// assert!(journal.undo());
+/// assert!(journal.section().is_empty());
+///
+/// assert!(journal.redo());
+/// assert_eq!(*journal.section().item(IVec3::new(1, 2, 3)).unwrap(), 5);
+/// ```
+pub struct Journal<T: PaletteItem, const W: usize, const H: usize, const D: usize> {
+    section: Section<T, W, H, D>,
+    palette: Palette<T>,
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+    depth: usize,
+}
+
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Journal<T, W, H, D> {
+    /// Starts a journal with no history around a section.
+    pub fn new(section: Section<T, W, H, D>) -> Self {
+        Self {
+            section,
+            palette: Palette::from_usage(Vec::new()),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            open: None,
+            depth: 0,
+        }
+    }
+
+    /// Returns the section being edited.
+    #[inline]
+    pub fn section(&self) -> &Section<T, W, H, D> {
+        &self.section
+    }
+
+    /// Drops the history and returns the section.
+    pub fn into_section(self) -> Section<T, W, H, D> {
+        self.section
+    }
+
+    /// Returns the number of transactions that can be undone.
+    #[inline]
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Returns the number of transactions that can be redone.
+    #[inline]
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Returns the number of distinct values referenced by the history.
+    #[inline]
+    pub fn palette_len(&self) -> usize {
+        self.palette.live_len()
+    }
+
+    /// Starts grouping edits into one transaction until the matching [`Journal::commit`].
+    ///
+    /// Transactions may be nested, only the outermost one is recorded.
+    pub fn begin(&mut self) {
+        self.depth += 1;
+        self.open.get_or_insert_with(Transaction::default);
+    }
+
+    /// Ends the transaction started by the matching [`Journal::begin`].
+    ///
+    /// Does nothing if no transaction is open.
+    pub fn commit(&mut self) {
+        match self.depth {
+            0 => {}
+            1 => {
+                self.depth = 0;
+                if let Some(transaction) = self.open.take() {
+                    self.push(transaction);
+                }
+            }
+            _ => {
+                self.depth -= 1;
+            }
+        }
+    }
+
+    /// Sets an item, recording the value it replaces.
+    /// Returns an error if position is out of the section bounds.
+    pub fn set_item(&mut self, pos: IVec3, item: T) -> Result<(), BoundsError> {
+        let old: &T = self.section.item(pos)?;
+        if old == &item {
+            return Ok(());
+        }
+
+        let old: u32 = Self::intern(&mut self.palette, old);
+        let new: u32 = Self::intern(&mut self.palette, &item);
+        self.section.set_item(pos, item).expect("position was checked");
+
+        self.record(Edit::Item {
+            item_index: Section::<T, W, H, D>::item_index(pos) as u32,
+            old,
+            new,
+        });
+        Ok(())
+    }
+
+    /// Sets every item in the box between two corners, both inclusive,
+    /// recording the replaced items as runs.
+    /// Returns an error if either corner is out of the section bounds.
+    pub fn fill_region(&mut self, min: IVec3, max: IVec3, item: T) -> Result<(), BoundsError> {
+        Section::<T, W, H, D>::check_position_in_bounds(min)?;
+        Section::<T, W, H, D>::check_position_in_bounds(max)?;
+        if min.cmpgt(max).any() {
+            return Ok(());
+        }
+
+        let mut old: Vec<(u32, u32)> = Vec::new();
+        let mut is_changed: bool = false;
+        for (start, len) in Section::<T, W, H, D>::region_runs(min, max) {
+            for item_index in start..start + len {
+                let old_item: &T = unsafe { self.section.item_at(item_index) };
+                is_changed |= old_item != &item;
+
+                match old.last_mut() {
+                    Some((run_len, palette_index)) if
+                        unsafe { self.palette.get_unchecked(*palette_index as usize) } == old_item
+                    => {
+                        *run_len += 1;
+                    }
+                    _ => {
+                        old.push((1, Self::intern(&mut self.palette, old_item)));
+                    }
+                }
+            }
+        }
+
+        if !is_changed {
+            Self::release_runs(&mut self.palette, &old);
+            return Ok(());
+        }
+
+        let new: u32 = Self::intern(&mut self.palette, &item);
+        self.section.fill_region(min, max, item).expect("corners were checked");
+        self.record(Edit::Region { min, max, old, new });
+        Ok(())
+    }
+
+    /// Sets every item in the section, recording the replaced items as runs.
+    pub fn fill(&mut self, item: T) {
+        let max: IVec3 = self.section.dimensions() - IVec3::ONE;
+        self.fill_region(IVec3::ZERO, max, item).expect("section corners are in bounds");
+    }
+
+    /// Reverts the latest transaction, returning false if there is nothing to undo.
+    ///
+    /// Any open transaction is committed first.
+    pub fn undo(&mut self) -> bool {
+        self.close();
+        let Some(transaction) = self.undo.pop() else {
+            return false;
+        };
+
+        for edit in transaction.edits.iter().rev() {
+            self.revert(edit);
+        }
+        self.redo.push(transaction);
+        true
+    }
+
+    /// Reapplies the latest undone transaction, returning false if there is nothing to redo.
+    ///
+    /// Any open transaction is committed first, which discards everything that could be redone.
+    pub fn redo(&mut self) -> bool {
+        self.close();
+        let Some(transaction) = self.redo.pop() else {
+            return false;
+        };
+
+        for edit in &transaction.edits {
+            self.apply(edit);
+        }
+        self.undo.push(transaction);
+        true
+    }
+
+    /// Forgets every recorded transaction, keeping the section as it is.
+    pub fn clear_history(&mut self) {
+        self.close();
+        self.undo.clear();
+        self.redo.clear();
+        self.palette = Palette::from_usage(Vec::new());
+    }
+
+    // commits any open transaction regardless of nesting
+    fn close(&mut self) {
+        if self.depth > 0 {
+            self.depth = 1;
+            self.commit();
+        }
+    }
+
+    // adds an edit to the open transaction, or records it as its own
+    fn record(&mut self, edit: Edit) {
+        match &mut self.open {
+            Some(transaction) => transaction.edits.push(edit),
+            None => self.push(Transaction { edits: vec![edit] }),
+        }
+    }
+
+    // records a finished transaction, dropping everything that could be redone
+    fn push(&mut self, transaction: Transaction) {
+        if transaction.edits.is_empty() {
+            return;
+        }
+
+        for dropped in std::mem::take(&mut self.redo) {
+            for edit in &dropped.edits {
+                Self::release(&mut self.palette, edit);
+            }
+        }
+        self.undo.push(transaction);
+    }
+
+    fn apply(&mut self, edit: &Edit) {
+        match *edit {
+            Edit::Item { item_index, new, .. } => {
+                let item: T = self.item(new);
+                unsafe {
+                    self.section.set_item_at(item_index as usize, item);
+                }
+            }
+            Edit::Region { min, max, new, .. } => {
+                let item: T = self.item(new);
+                self.section.fill_region(min, max, item).expect("recorded corners are in bounds");
+            }
+        }
+    }
+
+    fn revert(&mut self, edit: &Edit) {
+        match edit {
+            &Edit::Item { item_index, old, .. } => {
+                let item: T = self.item(old);
+                unsafe {
+                    self.section.set_item_at(item_index as usize, item);
+                }
+            }
+            Edit::Region { min, max, old, .. } => {
+                let mut runs = old.iter().flat_map(|&(len, palette_index)| {
+                    std::iter::repeat_n(palette_index, len as usize)
+                });
+
+                for (start, len) in Section::<T, W, H, D>::region_runs(*min, *max) {
+                    for item_index in start..start + len {
+                        let palette_index: u32 = runs.next().expect("runs cover the region");
+                        let item: T = unsafe {
+                            self.palette.get_unchecked(palette_index as usize).clone()
+                        };
+                        unsafe {
+                            self.section.set_item_at(item_index, item);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn item(&self, palette_index: u32) -> T {
+        unsafe { self.palette.get_unchecked(palette_index as usize).clone() }
+    }
+
+    // adds a reference to an item, storing it if it is new
+    fn intern(palette: &mut Palette<T>, item: &T) -> u32 {
+        let palette_index: usize = palette
+            .index_of(item)
+            .unwrap_or_else(|| palette.insert(item.clone()));
+        palette.acquire(palette_index);
+        u32::try_from(palette_index).expect("journal palette is full")
+    }
+
+    fn release(palette: &mut Palette<T>, edit: &Edit) {
+        match edit {
+            &Edit::Item { old, new, .. } => {
+                palette.release(old as usize);
+                palette.release(new as usize);
+            }
+            Edit::Region { old, new, .. } => {
+                Self::release_runs(palette, old);
+                palette.release(*new as usize);
+            }
+        }
+    }
+
+    fn release_runs(palette: &mut Palette<T>, runs: &[(u32, u32)]) {
+        for &(_, palette_index) in runs {
+            palette.release(palette_index as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Small = Section<u64, 4, 4, 4>;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut journal: Journal<u64, 4, 4, 4> = Journal::new(Small::new(1));
+        journal.set_item(IVec3::new(0, 1, 2), 3).unwrap();
+        journal.set_item(IVec3::new(0, 1, 2), 4).unwrap();
+        journal.set_item(IVec3::new(0, 1, 2), 4).unwrap();
+        assert_eq!(journal.undo_len(), 2);
+
+        assert!(journal.undo());
+        assert_eq!(*journal.section().item(IVec3::new(0, 1, 2)).unwrap(), 3);
+        assert!(journal.undo());
+        assert!(journal.section().is_empty());
+        assert!(!journal.undo());
+
+        assert!(journal.redo());
+        assert_eq!(*journal.section().item(IVec3::new(0, 1, 2)).unwrap(), 3);
+
+        journal.set_item(IVec3::new(3, 3, 3), 5).unwrap();
+        assert_eq!(journal.redo_len(), 0);
+        assert!(!journal.redo());
+        assert_eq!(journal.palette_len(), 3);
+    }
+
+    #[test]
+    fn test_region_undo() {
+        let mut section: Small = Small::new(2);
+        section.fill_with(|pos| (pos.x + pos.y * 4) as u64);
+        let before: Vec<u64> = section.values().copied().collect();
+
+        let mut journal: Journal<u64, 4, 4, 4> = Journal::new(section);
+        journal.fill_region(IVec3::new(1, 0, 0), IVec3::new(2, 2, 3), 9).unwrap();
+        journal.fill(7);
+        assert_eq!(journal.section().count_of(&7), 64);
+
+        assert!(journal.undo());
+        assert_eq!(journal.section().count_of(&9), 2 * 3 * 4);
+        assert!(journal.undo());
+        assert!(journal.section().values().copied().eq(before));
+
+        assert!(journal.fill_region(IVec3::ZERO, IVec3::splat(4), 1).is_err());
+        journal.fill_region(IVec3::ZERO, IVec3::ZERO, 0).unwrap();
+        assert_eq!(journal.undo_len(), 0);
+    }
+
+    #[test]
+    fn test_transactions() {
+        let mut journal: Journal<u64, 4, 4, 4> = Journal::new(Small::new(1));
+        journal.begin();
+        journal.set_item(IVec3::new(0, 0, 0), 1).unwrap();
+        journal.begin();
+        journal.fill_region(IVec3::new(1, 1, 1), IVec3::new(2, 2, 2), 2).unwrap();
+        journal.commit();
+        assert_eq!(journal.undo_len(), 0);
+        journal.commit();
+        assert_eq!(journal.undo_len(), 1);
+
+        journal.begin();
+        journal.commit();
+        assert_eq!(journal.undo_len(), 1);
+
+        journal.begin();
+        journal.set_item(IVec3::new(3, 3, 3), 3).unwrap();
+        assert!(journal.undo());
+        assert_eq!(*journal.section().item(IVec3::new(0, 0, 0)).unwrap(), 1);
+        assert!(journal.undo());
+        assert!(journal.section().is_empty());
+
+        journal.clear_history();
+        assert_eq!(journal.redo_len(), 0);
+        assert_eq!(journal.palette_len(), 0);
+    }
+}
@@ -1,4 +1,37 @@
+pub mod binary;
+mod builder;
+mod changes;
+mod error;
+mod iter;
+mod packed;
+mod journal;
+pub mod mesh;
+mod palette;
+mod patch;
+mod shared;
+mod validate;
+mod world;
+
+pub use binary::{ BinaryItem, FormatError };
+pub use builder::SectionBuilder;
+pub use changes::Change;
+pub use error::Error;
+pub use iter::Values;
+pub use journal::Journal;
+pub use mesh::{ Face, Mesh, Neighbors };
+pub use palette::PaletteItem;
+pub use patch::SectionPatch;
+pub use shared::{ GlobalId, SharedPalette, SharedSection };
+pub use validate::ValidationError;
+pub use world::World;
+
+use changes::ChangeTracker;
 use glam::IVec3;
+use packed::PackedArray;
+use palette::Palette;
+use std::collections::HashMap;
+use std::hash::{ DefaultHasher, Hash, Hasher };
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -6,21 +39,119 @@ pub enum BoundsError {
     #[error("Position {0:?} is out of bounds for the section.")] OutOfBounds(IVec3),
 }
 
+/// Controls when a section reclaims palette entries that are no longer referenced.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompactionPolicy {
+    /// Unused entries are only removed by calling [`Section::compact`].
+    #[default]
+    Manual,
+    /// The section compacts itself once its live entries fit in
+    /// a quarter of the palette, so shrinking never immediately undoes itself.
+    WhenSparse,
+}
+
+/// How packed items are laid out in memory.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// Items are packed back to back and may straddle two words, using the least memory.
+    #[default]
+    Spanning,
+    /// Each word holds `64 / bits_per_item` whole items, leaving any remaining bits unused.
+    /// Access never touches two words.
+    Aligned,
+}
+
+/// Controls how many bits per item a section grows to once its palette is full.
+///
+/// The result is always at least the bits the palette needs,
+/// then kept within the section's minimum and maximum bits per item.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum GrowthPolicy {
+    /// Grows by a single bit, keeping memory tight at the cost of more repacks.
+    #[default]
+    Increment,
+    /// Doubles the bits per item.
+    Double,
+    /// Jumps to the next width of 4, 8, 16, 32 or 64 bits.
+    ByteAligned,
+    /// Calls the function with the current and required bits per item.
+    Custom(fn(u8, u8) -> u8),
+}
+
+impl GrowthPolicy {
+    /// Returns the bits per item to grow to from `current` when at least `required` are needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chroma::GrowthPolicy;
+    ///
+    /// assert_eq!(GrowthPolicy::Increment.grow(3, 4), 4);
+    /// assert_eq!(GrowthPolicy::Double.grow(3, 4), 6);
+    /// assert_eq!(GrowthPolicy::ByteAligned.grow(4, 5), 8);
+    /// assert_eq!(GrowthPolicy::Custom(|_, required| required + 2).grow(3, 4), 6);
+    /// ```
+    pub fn grow(self, current: u8, required: u8) -> u8 {
+        let grown: u8 = match self {
+            Self::Increment => current.saturating_add(1),
+            Self::Double => current.saturating_mul(2),
+            Self::ByteAligned =>
+                [4, 8, 16, 32, 64]
+                    .into_iter()
+                    .find(|&width| width >= required)
+                    .unwrap_or(64),
+            Self::Custom(grow) => grow(current, required),
+        };
+
+        grown.max(required)
+    }
+}
+
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone)]
-pub struct Section<const W: usize, const H: usize, const D: usize> {
-    data: Vec<u64>,
-    palette: Vec<u64>,
-    bits_per_item: u8,
+enum Storage<T: PaletteItem> {
+    /// Every item holds the same value, nothing is allocated.
+    Uniform(T),
+    /// Items are packed indices into a palette of values.
+    Indirect {
+        palette: Palette<T>,
+        data: PackedArray,
+    },
+    /// Items are stored whole with no palette.
+    Direct(Vec<T>),
 }
 
-impl<const W: usize, const H: usize, const D: usize> Section<W, H, D> {
-    const VOLUME: usize = W * H * D;
-    const BITS_PER_WORD: usize = 64;
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "validate::RawSection<T>"))]
+#[derive(Clone)]
+pub struct Section<T: PaletteItem, const W: usize, const H: usize, const D: usize> {
+    storage: Arc<Storage<T>>,
+    initial_bits_per_item: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    compaction_policy: CompactionPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    direct_threshold: Option<u8>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    growth_policy: GrowthPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    min_bits_per_item: u8,
+    #[cfg_attr(feature = "serde", serde(default = "SectionBuilder::max_bits_per_item_default"))]
+    max_bits_per_item: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    layout: Layout,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    changes: Option<Box<ChangeTracker<T>>>,
+}
 
-    /// Creates a new section given dimensions and initial bits per item.
+impl<T: PaletteItem + Default, const W: usize, const H: usize, const D: usize> Section<T, W, H, D> {
+    /// Creates a new section given dimensions and initial bits per item,
+    /// with every item set to its default value.
     ///
     /// The more bits per item the more memory but less likely to repack.
+    /// Nothing is allocated until a second distinct item is set.
+    /// Bits per item above 64 are treated as 64.
     ///
     /// # Examples
     ///
@@ -28,7 +159,7 @@ impl<const W: usize, const H: usize, const D: usize> Section<W, H, D> {
     /// use glam::IVec3;
     /// use chroma::Section;
     ///
-    /// let mut section: Section<16, 16, 16> = Section::new(2);
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(2);
     /// assert!(section.is_empty());
     ///
     /// let pos: IVec3 = IVec3::new(0, 0, 0);
@@ -36,21 +167,110 @@ impl<const W: usize, const H: usize, const D: usize> Section<W, H, D> {
     /// assert!(!section.is_empty());
     /// ```
     pub fn new(bits_per_item: u8) -> Self {
-        let palette_len: usize = 1 << bits_per_item;
-        let total_bits_needed: usize = (bits_per_item as usize) * Self::VOLUME;
-        let data_len: usize = total_bits_needed / Self::BITS_PER_WORD + 1;
+        Self::filled(T::default(), bits_per_item)
+    }
+
+    /// Creates a new section that reclaims unused palette entries according to `policy`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::{ CompactionPolicy, Section };
+    ///
+    /// let mut section: Section<u64, 4, 4, 4> =
+    ///     Section::with_compaction(0, CompactionPolicy::WhenSparse);
+    ///
+    /// for x in 0..4 {
+    ///     section.set_item(IVec3::new(x, 0, 0), (x as u64) + 1).unwrap();
+    /// }
+    /// assert_eq!(section.bits_per_item(), 3);
+    ///
+    /// for x in 0..4 {
+    ///     section.set_item(IVec3::new(x, 0, 0), 0).unwrap();
+    /// }
+    /// assert_eq!(section.bits_per_item(), 1);
+    /// ```
+    pub fn with_compaction(bits_per_item: u8, compaction_policy: CompactionPolicy) -> Self {
+        let mut section: Self = Self::new(bits_per_item);
+        section.compaction_policy = compaction_policy;
+        section
+    }
+
+    /// Returns if there is only one item type and it is the default value.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count_of(&T::default()) == Self::VOLUME
+    }
+
+    /// Iterates over every position whose item is not the default value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+    /// section.set_item(IVec3::new(3, 4, 5), 1).unwrap();
+    ///
+    /// let nonzero: Vec<(IVec3, &u64)> = section.iter_nonzero().collect();
+    /// assert_eq!(nonzero, [(IVec3::new(3, 4, 5), &1)]);
+    /// ```
+    pub fn iter_nonzero(&self) -> impl Iterator<Item = (IVec3, &T)> + '_ {
+        let default: T = T::default();
+        self.iter().filter(move |&(_, item)| item != &default)
+    }
+}
 
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Section<T, W, H, D> {
+    const VOLUME: usize = W * H * D;
+
+    /// Edge length of the cubes of items that changes are grouped into,
+    /// see [`Section::dirty_regions`].
+    pub const DIRTY_REGION_SIZE: usize = 4;
+
+    /// Creates a new section given dimensions and initial bits per item,
+    /// with every item set to `item`.
+    /// Bits per item above 64 are treated as 64.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let section: Section<&str, 4, 4, 4> = Section::filled("air", 1);
+    /// assert_eq!(section.item(IVec3::new(1, 2, 3)).unwrap(), &"air");
+    /// ```
+    pub fn filled(item: T, bits_per_item: u8) -> Self {
         Self {
-            data: vec![0; data_len],
-            palette: vec![0; palette_len],
-            bits_per_item,
+            storage: Arc::new(Storage::Uniform(item)),
+            initial_bits_per_item: bits_per_item.min(64),
+            compaction_policy: CompactionPolicy::default(),
+            direct_threshold: None,
+            growth_policy: GrowthPolicy::default(),
+            min_bits_per_item: 0,
+            max_bits_per_item: SectionBuilder::max_bits_per_item_default(),
+            layout: Layout::default(),
+            changes: None,
         }
     }
 
-    /// Returns if there is only one item type and it has a value of zero.
+    /// Returns if every item in the section holds the same value.
     #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.data.iter().all(|&word| word == 0)
+    pub fn is_uniform(&self) -> bool {
+        match &*self.storage {
+            Storage::Uniform(_) => true,
+            Storage::Indirect { palette, .. } => palette.live_len() == 1,
+            Storage::Direct(data) => data.iter().all(|item| item == &data[0]),
+        }
+    }
+
+    /// Returns if items are stored whole rather than as palette indices.
+    #[inline]
+    pub fn is_direct(&self) -> bool {
+        matches!(*self.storage, Storage::Direct(_))
     }
 
     /// Returns the dimensions (width, height, depth) of the section.
@@ -65,214 +285,2212 @@ impl<const W: usize, const H: usize, const D: usize> Section<W, H, D> {
         Self::VOLUME
     }
 
-    /// Gets an item given its three dimensional position.
+    /// Returns the number of bits currently used to store each palette index.
+    ///
+    /// Uniform and direct sections pack no indices.
     #[inline]
-    pub fn item(&self, pos: IVec3) -> Result<u64, BoundsError> {
-        Self::check_position_in_bounds(pos)?;
-        Ok(unsafe { self.item_unchecked(pos) })
+    pub fn bits_per_item(&self) -> u8 {
+        match &*self.storage {
+            Storage::Uniform(_) | Storage::Direct(_) => 0,
+            Storage::Indirect { data, .. } => data.bits_per_item(),
+        }
     }
 
-    /// Gets an item given its three dimensional position.
+    /// Returns the number of entries in the palette, including unused ones.
     ///
-    /// # Panics
-    ///
-    /// Will be unchecked and may panic if position is out of bounds.
+    /// A direct section has no palette.
     #[inline]
-    pub unsafe fn item_unchecked(&self, pos: IVec3) -> u64 {
-        let item_index: usize = Self::item_index(pos);
-        let palette_index: usize = self.palette_index(item_index);
-        unsafe { *self.palette.get_unchecked(palette_index) }
-    }
-
-    /// Sets an item at the given three dimensional position.
-    /// Returns an error if position is out of the section bounds.
-    #[must_use]
-    pub fn set_item(&mut self, pos: IVec3, item: u64) -> Result<(), BoundsError> {
-        Self::check_position_in_bounds(pos)?;
-        unsafe {
-            self.set_item_unchecked(pos, item);
+    pub fn palette_len(&self) -> usize {
+        match &*self.storage {
+            Storage::Uniform(_) => 1,
+            Storage::Indirect { palette, .. } => palette.len(),
+            Storage::Direct(_) => 0,
         }
-        Ok(())
     }
 
-    /// Sets an item at the given three dimensional position.
+    /// Returns how many items in the section are equal to `item`.
     ///
-    /// # Panics
+    /// Constant time unless the section is direct, which has to scan every item.
     ///
-    /// Will be unchecked and may panic if position is out of bounds.
-    pub unsafe fn set_item_unchecked(&mut self, pos: IVec3, item: u64) {
-        let palette_index = self.palette
-            .iter()
-            .position(|&id| id == item)
-            .unwrap_or_else(|| {
-                let new_index: usize = self.palette.len();
-                self.palette.push(item);
-
-                if 1 << self.bits_per_item <= new_index {
-                    self.repack(self.bits_per_item + 1);
-                }
-
-                new_index
-            });
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(2);
+    /// section.set_item(IVec3::new(0, 0, 0), 7).unwrap();
+    ///
+    /// assert_eq!(section.count_of(&7), 1);
+    /// assert_eq!(section.count_of(&0), section.volume() - 1);
+    /// assert_eq!(section.count_of(&3), 0);
+    /// ```
+    #[inline]
+    pub fn count_of(&self, item: &T) -> usize {
+        match &*self.storage {
+            Storage::Uniform(uniform) if uniform == item => Self::VOLUME,
+            Storage::Uniform(_) => 0,
+            Storage::Indirect { palette, .. } => palette.count_of(item),
+            Storage::Direct(data) => data.iter().filter(|&value| value == item).count(),
+        }
+    }
 
-        let item_index: usize = Self::item_index(pos);
+    /// Iterates over every distinct item in the section and how many times it occurs.
+    #[inline]
+    pub fn palette_usage(&self) -> impl Iterator<Item = (&T, usize)> + '_ {
+        let (uniform, palette, direct) = match &*self.storage {
+            Storage::Uniform(uniform) => (Some((uniform, Self::VOLUME)), None, None),
+            Storage::Indirect { palette, .. } => (None, Some(palette.usage()), None),
+            Storage::Direct(data) => (None, None, Some(Self::direct_usage(data))),
+        };
 
-        unsafe {
-            self.set_item_ex(item_index, palette_index);
-        }
+        uniform
+            .into_iter()
+            .chain(palette.into_iter().flatten())
+            .chain(direct.into_iter().flatten())
     }
 
-    unsafe fn set_item_ex(&mut self, item_index: usize, palette_index: usize) {
-        debug_assert!(palette_index < 1usize << self.bits_per_item, "repack needed first");
+    /// Returns the policy used to reclaim unused palette entries.
+    #[inline]
+    pub const fn compaction_policy(&self) -> CompactionPolicy {
+        self.compaction_policy
+    }
 
-        let (word_index, bit_in_word) = Self::split_index(item_index, self.bits_per_item);
-        let bits_in_first_word: usize = Self::BITS_PER_WORD - bit_in_word;
+    /// Sets the policy used to reclaim unused palette entries.
+    #[inline]
+    pub fn set_compaction_policy(&mut self, compaction_policy: CompactionPolicy) {
+        self.compaction_policy = compaction_policy;
+    }
 
-        unsafe {
-            if (self.bits_per_item as usize) <= bits_in_first_word {
-                let item_mask: u64 = (1u64 << self.bits_per_item).wrapping_sub(1);
-                *self.data.get_unchecked_mut(word_index) &= !(item_mask << bit_in_word);
-                *self.data.get_unchecked_mut(word_index) |=
-                    ((palette_index as u64) & item_mask) << bit_in_word;
-            } else {
-                let bits_in_second_word: usize = (self.bits_per_item as usize) - bits_in_first_word;
-                let mask_for_first_word: u64 = (1u64 << bits_in_first_word).wrapping_sub(1);
-                *self.data.get_unchecked_mut(word_index) &= !(mask_for_first_word << bit_in_word);
-                *self.data.get_unchecked_mut(word_index) |=
-                    ((palette_index as u64) & mask_for_first_word) << bit_in_word;
+    /// Returns the policy used to grow the bits per item.
+    #[inline]
+    pub const fn growth_policy(&self) -> GrowthPolicy {
+        self.growth_policy
+    }
 
-                debug_assert!(
-                    word_index + 1 < self.data.len(),
-                    "should not write beyond data bounds"
-                );
+    /// Returns the fewest bits per item a packed palette is stored with.
+    #[inline]
+    pub const fn min_bits_per_item(&self) -> u8 {
+        self.min_bits_per_item
+    }
 
-                let mask_for_second_word: u64 = (1u64 << bits_in_second_word).wrapping_sub(1);
-                *self.data.get_unchecked_mut(word_index + 1) &= !mask_for_second_word;
-                *self.data.get_unchecked_mut(word_index + 1) |=
-                    ((palette_index as u64) >> bits_in_first_word) & mask_for_second_word;
-            }
-        }
+    /// Returns the most bits per item the growth policy may choose.
+    ///
+    /// A palette that needs more bits than this still grows to what it needs.
+    #[inline]
+    pub const fn max_bits_per_item(&self) -> u8 {
+        self.max_bits_per_item
     }
 
+    /// Returns how packed items are laid out in memory.
     #[inline]
-    const fn split_index(item_index: usize, bits_per_item: u8) -> (usize, usize) {
-        let bit_offset: usize = item_index * (bits_per_item as usize);
-        let word_index: usize = bit_offset / Self::BITS_PER_WORD;
-        let bit_in_word: usize = bit_offset % Self::BITS_PER_WORD;
-        (word_index, bit_in_word)
+    pub const fn layout(&self) -> Layout {
+        self.layout
     }
 
+    /// Returns the bits per item above which the palette is dropped, if any.
     #[inline]
-    const fn item_index(pos: IVec3) -> usize {
-        (pos.x as usize) * (H * D) + (pos.y as usize) * D + (pos.z as usize)
+    pub const fn direct_threshold(&self) -> Option<u8> {
+        self.direct_threshold
+    }
+
+    /// Sets the bits per item above which the palette is dropped
+    /// and items are stored directly, or `None` to always keep a palette.
+    ///
+    /// A section already above the new threshold switches immediately.
+    /// Call [`Section::compact`] to return to a palette once few enough values remain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+    /// section.set_direct_threshold(Some(4));
+    ///
+    /// for z in 0..16 {
+    ///     section.set_item(IVec3::new(0, 0, z), (z as u64) * 100).unwrap();
+    /// }
+    /// assert!(!section.is_direct());
+    ///
+    /// section.set_item(IVec3::new(0, 1, 0), 1600).unwrap();
+    /// assert!(section.is_direct());
+    /// assert_eq!(section.item(IVec3::new(0, 0, 15)).unwrap(), &1500);
+    /// ```
+    pub fn set_direct_threshold(&mut self, direct_threshold: Option<u8>) {
+        self.direct_threshold = direct_threshold;
+        self.apply_direct_threshold();
     }
 
+    /// Returns if changed items are being recorded.
     #[inline]
-    fn palette_index(&self, item_index: usize) -> usize {
-        let (word_index, bit_in_word) = Self::split_index(item_index, self.bits_per_item);
+    pub fn is_tracking_changes(&self) -> bool {
+        self.changes.is_some()
+    }
 
-        let mut item: u64 = self.data[word_index];
+    /// Starts or stops recording which items change.
+    ///
+    /// Stopping discards any changes not yet taken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::{ Change, Section };
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+    /// section.set_track_changes(true);
+    ///
+    /// section.set_item(IVec3::new(5, 6, 7), 3).unwrap();
+    /// assert!(section.is_changed());
+    /// assert_eq!(section.dirty_regions().collect::<Vec<IVec3>>(), [IVec3::new(1, 1, 1)]);
+    ///
+    /// let changes: Vec<Change<u64>> = section.take_changes();
+    /// assert_eq!(changes, [Change { pos: IVec3::new(5, 6, 7), old: 0, new: 3 }]);
+    /// assert!(!section.is_changed());
+    /// ```
+    pub fn set_track_changes(&mut self, track_changes: bool) {
+        if track_changes == self.is_tracking_changes() {
+            return;
+        }
 
-        if bit_in_word + (self.bits_per_item as usize) > Self::BITS_PER_WORD {
-            item >>= bit_in_word;
-            let remaining_bits_n: usize =
-                bit_in_word + (self.bits_per_item as usize) - Self::BITS_PER_WORD;
-            let next_word: u64 = self.data[word_index + 1];
-            item |= next_word << ((self.bits_per_item as usize) - remaining_bits_n);
+        self.changes = if track_changes {
+            let regions: IVec3 = Self::regions();
+            Some(Box::new(ChangeTracker::new(regions.element_product() as usize)))
         } else {
-            item >>= bit_in_word;
-        }
+            None
+        };
+    }
 
-        let mask: u64 = (1 << self.bits_per_item) - 1;
-        (item & mask) as usize
+    /// Returns if any item has changed since changes were last taken.
+    ///
+    /// Always false unless changes are being tracked.
+    #[inline]
+    pub fn is_changed(&self) -> bool {
+        self.changes.as_ref().is_some_and(|changes| changes.is_changed())
     }
 
-    // adjusts the data to account for a new amount of bits per item
-    fn repack(&mut self, new_bits_per_item: u8) {
-        debug_assert!(self.bits_per_item <= new_bits_per_item, "repack must increase bits");
+    /// Returns if any item in a dirty region has changed since changes were last taken.
+    ///
+    /// Regions are cubes of [`Section::DIRTY_REGION_SIZE`] items,
+    /// so the item at `pos` is in region `pos / DIRTY_REGION_SIZE`.
+    /// Always false unless changes are being tracked.
+    pub fn is_region_dirty(&self, region: IVec3) -> bool {
+        let regions: IVec3 = Self::regions();
+        if region.cmplt(IVec3::ZERO).any() || region.cmpge(regions).any() {
+            return false;
+        }
 
-        let all_palette_indices: Vec<usize> = (0..Self::VOLUME)
-            .map(|item_index| self.palette_index(item_index))
-            .collect();
+        let region_index: usize = (region.x * regions.y * regions.z +
+            region.y * regions.z +
+            region.z) as usize;
+        self.changes.as_ref().is_some_and(|changes| changes.is_region_dirty(region_index))
+    }
 
-        self.bits_per_item = new_bits_per_item;
-        let new_total_bits_needed: usize = (self.bits_per_item as usize) * Self::VOLUME;
-        let new_data_len: usize = new_total_bits_needed / Self::BITS_PER_WORD + 1;
-        self.data = vec![0; new_data_len];
+    /// Iterates over every dirty region, see [`Section::is_region_dirty`].
+    pub fn dirty_regions(&self) -> impl Iterator<Item = IVec3> + '_ {
+        let regions: IVec3 = Self::regions();
 
-        for item_index in 0..Self::VOLUME {
-            unsafe {
-                let palette_index: usize = *all_palette_indices.get_unchecked(item_index);
-                self.set_item_ex(item_index, palette_index);
-            }
-        }
+        (0..regions.x).flat_map(move |x| {
+            (0..regions.y).flat_map(move |y| {
+                (0..regions.z)
+                    .map(move |z| IVec3::new(x, y, z))
+                    .filter(|&region| self.is_region_dirty(region))
+            })
+        })
     }
 
-    #[must_use]
-    const fn check_position_in_bounds(pos: IVec3) -> Result<(), BoundsError> {
-        if
-            pos.x < 0 ||
-            pos.x >= (W as i32) ||
-            pos.y < 0 ||
-            pos.y >= (H as i32) ||
-            pos.z < 0 ||
-            pos.z >= (D as i32)
-        {
-            Err(BoundsError::OutOfBounds(pos))
-        } else {
-            Ok(())
-        }
+    /// Returns every item changed since changes were last taken with its old and new value,
+    /// ordered like [`Section::iter`], and clears the changed flag and dirty regions.
+    ///
+    /// Items changed back to the value they had are left out, though their region stays dirty.
+    /// Always empty unless changes are being tracked.
+    pub fn take_changes(&mut self) -> Vec<Change<T>> {
+        let Some(changes) = &mut self.changes else {
+            return Vec::new();
+        };
+
+        changes
+            .take()
+            .into_iter()
+            .map(|(item_index, old, new)| Change { pos: Self::position_of(item_index), old, new })
+            .collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Gets an item given its three dimensional position.
+    #[inline]
+    pub fn item(&self, pos: IVec3) -> Result<&T, BoundsError> {
+        Self::check_position_in_bounds(pos)?;
+        Ok(unsafe { self.item_unchecked(pos) })
+    }
 
-    #[test]
-    fn test_new_is_empty() {
-        let section: Section<16, 16, 16> = Section::new(2);
-        assert!(section.is_empty());
+    /// Gets an item given its three dimensional position.
+    ///
+    /// # Safety
+    ///
+    /// Position must be within the section bounds, no checks are made.
+    #[inline]
+    pub unsafe fn item_unchecked(&self, pos: IVec3) -> &T {
+        unsafe { self.item_at(Self::item_index(pos)) }
     }
 
-    #[test]
-    fn test_set_and_get_item() {
-        let mut section: Section<16, 16, 16> = Section::new(4);
-        let pos_1: IVec3 = IVec3::new(15, 1, 1);
-        let pos_2: IVec3 = IVec3::new(15, 1, 2);
+    // gets the item at a flat index into the section
+    unsafe fn item_at(&self, item_index: usize) -> &T {
+        match &*self.storage {
+            Storage::Uniform(uniform) => uniform,
+            Storage::Indirect { palette, data } => {
+                let palette_index: usize = data.get(item_index) as usize;
+                unsafe { palette.get_unchecked(palette_index) }
+            }
+            Storage::Direct(data) => unsafe { data.get_unchecked(item_index) },
+        }
+    }
 
+    /// Sets an item at the given three dimensional position.
+    /// Returns an error if position is out of the section bounds.
+    pub fn set_item(&mut self, pos: IVec3, item: T) -> Result<(), BoundsError> {
+        Self::check_position_in_bounds(pos)?;
         unsafe {
-            section.set_item_unchecked(pos_1, 3);
-            section.set_item_unchecked(pos_1, 2);
-            section.set_item_unchecked(pos_2, 1);
+            self.set_item_unchecked(pos, item);
+        }
+        Ok(())
+    }
 
-            assert_eq!(section.item_unchecked(pos_1), 2);
-            assert_eq!(section.item_unchecked(pos_2), 1);
+    /// Sets an item at the given three dimensional position.
+    ///
+    /// # Safety
+    ///
+    /// Position must be within the section bounds, no checks are made.
+    pub unsafe fn set_item_unchecked(&mut self, pos: IVec3, item: T) {
+        unsafe {
+            self.set_item_at(Self::item_index(pos), item);
         }
     }
 
-    #[test]
-    fn test_repack() {
-        let mut section: Section<16, 16, 16> = Section::new(1);
-        let pos: IVec3 = IVec3::new(3, 5, 3);
+    // sets the item at a flat index into the section, recording the change if tracked
+    unsafe fn set_item_at(&mut self, item_index: usize, item: T) {
+        if self.changes.is_none() {
+            unsafe {
+                self.write_item_at(item_index, item);
+            }
+            return;
+        }
 
+        let old: T = unsafe { self.item_at(item_index) }.clone();
+        if old == item {
+            return;
+        }
         unsafe {
-            section.set_item_unchecked(pos, 30);
-            assert_eq!(section.item_unchecked(pos), 30);
+            self.write_item_at(item_index, item.clone());
+        }
+
+        let region_index: usize = Self::region_index(item_index);
+        if let Some(changes) = &mut self.changes {
+            changes.record(item_index, region_index, old, item);
         }
     }
 
-    #[test]
-    fn test_max_fill() {
-        let mut section: Section<16, 16, 16> = Section::new(0);
+    unsafe fn write_item_at(&mut self, item_index: usize, item: T) {
+        // checked before the storage is copied out of a snapshot
+        if unsafe { self.item_at(item_index) } == &item {
+            return;
+        }
+        if matches!(*self.storage, Storage::Uniform(_)) {
+            self.promote();
+        }
 
-        for x in 0..16 {
-            for y in 0..16 {
-                for z in 0..16 {
-                    let pos: IVec3 = IVec3::new(x, y, z);
-                    section.set_item(pos, (x + y + z) as u64).unwrap();
+        if let Storage::Indirect { palette, data } = Arc::make_mut(&mut self.storage) {
+            let old_palette_index: usize = data.get(item_index) as usize;
+
+            if unsafe { palette.get_unchecked(old_palette_index) } == &item {
+                return;
+            }
+
+            // released first so a value that just lost its last cell frees a slot for the new one
+            palette.release(old_palette_index);
+
+            let existing_index: Option<usize> = palette.index_of(&item);
+            let is_full: bool =
+                Self::palette_capacity(data.bits_per_item()) <= palette.next_index();
+
+            let new_bits_per_item: Option<u8> = if existing_index.is_none() && is_full {
+                Self::grown_bits_per_item(
+                    self.growth_policy,
+                    self.min_bits_per_item,
+                    self.max_bits_per_item,
+                    self.direct_threshold,
+                    data.bits_per_item()
+                )
+            } else {
+                Some(data.bits_per_item())
+            };
+
+            if let Some(new_bits_per_item) = new_bits_per_item {
+                if new_bits_per_item != data.bits_per_item() {
+                    *data = data.repacked(Self::VOLUME, new_bits_per_item, |index| index);
+                }
+
+                let palette_index: usize = existing_index.unwrap_or_else(|| palette.insert(item));
+
+                palette.acquire(palette_index);
+
+                unsafe {
+                    data.set_unchecked(item_index, palette_index as u64);
+                }
+
+                if self.compaction_policy == CompactionPolicy::WhenSparse && self.is_sparse() {
+                    self.compact();
                 }
+                return;
+            }
+
+            self.make_direct();
+        }
+
+        if let Storage::Direct(data) = Arc::make_mut(&mut self.storage) {
+            unsafe {
+                *data.get_unchecked_mut(item_index) = item;
             }
         }
     }
+
+    /// Iterates over every position in the section and its item,
+    /// in the same x, y, z order as nested loops with z innermost.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 2, 2, 2> = Section::new(1);
+    /// section.set_item(IVec3::new(1, 0, 1), 4).unwrap();
+    ///
+    /// let (pos, item) = section.iter().nth(5).unwrap();
+    /// assert_eq!(pos, IVec3::new(1, 0, 1));
+    /// assert_eq!(*item, 4);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, &T)> + '_ {
+        Self::positions().zip(self.values())
+    }
+
+    /// Iterates over every item in the section, in the same order as [`Section::iter`].
+    pub fn values(&self) -> Values<'_, T> {
+        match &*self.storage {
+            Storage::Uniform(uniform) => Values::uniform(uniform, Self::VOLUME),
+            Storage::Indirect { palette, data } => {
+                Values::indirect(palette, data.iter(Self::VOLUME))
+            }
+            Storage::Direct(data) => Values::direct(data),
+        }
+    }
+
+    /// Calls `f` with every position and a mutable reference to its item,
+    /// storing back any items that were changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 4, 4, 4> = Section::new(1);
+    /// section.for_each_mut(|pos, item| *item = pos.y as u64);
+    ///
+    /// assert_eq!(*section.item(IVec3::new(2, 3, 1)).unwrap(), 3);
+    /// assert_eq!(section.count_of(&0), 16);
+    /// ```
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(IVec3, &mut T)) {
+        let mut changes: Vec<(usize, T)> = Vec::new();
+
+        for (item_index, (pos, item)) in self.iter().enumerate() {
+            let mut new_item: T = item.clone();
+            f(pos, &mut new_item);
+
+            if &new_item != item {
+                changes.push((item_index, new_item));
+            }
+        }
+
+        for (item_index, item) in changes {
+            unsafe {
+                self.set_item_at(item_index, item);
+            }
+        }
+    }
+
+    /// Sets every item in the section to the same value, releasing the packed data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(4);
+    /// section.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+    ///
+    /// section.fill(9);
+    /// assert!(section.is_uniform());
+    /// assert_eq!(section.count_of(&9), section.volume());
+    /// assert_eq!(section.bits_per_item(), 0);
+    /// ```
+    pub fn fill(&mut self, item: T) {
+        self.tracked(|section| {
+            section.storage = Arc::new(Storage::Uniform(item));
+        });
+    }
+
+    /// Sets every item in the box between two corners, both inclusive, to the same value.
+    /// Returns an error if either corner is out of the section bounds.
+    ///
+    /// The palette is looked up and grown at most once for the whole box,
+    /// and runs of packed items are written a word at a time where they can be.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+    /// section.fill_region(IVec3::new(0, 0, 0), IVec3::new(15, 3, 15), 2).unwrap();
+    ///
+    /// assert_eq!(section.count_of(&2), 16 * 4 * 16);
+    /// assert_eq!(*section.item(IVec3::new(7, 3, 9)).unwrap(), 2);
+    /// assert_eq!(*section.item(IVec3::new(7, 4, 9)).unwrap(), 0);
+    /// assert!(section.fill_region(IVec3::new(0, 0, 0), IVec3::new(16, 0, 0), 2).is_err());
+    /// ```
+    pub fn fill_region(&mut self, min: IVec3, max: IVec3, item: T) -> Result<(), BoundsError> {
+        Self::check_position_in_bounds(min)?;
+        Self::check_position_in_bounds(max)?;
+
+        self.tracked(|section| section.write_region(min, max, item));
+        Ok(())
+    }
+
+    // sets every item in a box already known to be in bounds
+    fn write_region(&mut self, min: IVec3, max: IVec3, item: T) {
+        if min.cmpgt(max).any() {
+            return;
+        }
+        if min == IVec3::ZERO && max == self.dimensions() - IVec3::ONE {
+            self.fill(item);
+            return;
+        }
+
+        if let Storage::Uniform(uniform) = &*self.storage {
+            if uniform == &item {
+                return;
+            }
+            self.promote();
+        }
+
+        if let Storage::Indirect { palette, data } = Arc::make_mut(&mut self.storage) {
+            let mut released: Vec<usize> = vec![0; palette.len()];
+            for (start, len) in Self::region_runs(min, max) {
+                for item_index in start..start + len {
+                    released[data.get(item_index) as usize] += 1;
+                }
+            }
+            for (palette_index, &count) in released.iter().enumerate() {
+                if count > 0 {
+                    palette.release_many(palette_index, count);
+                }
+            }
+
+            let existing_index: Option<usize> = palette.index_of(&item);
+            let is_full: bool =
+                Self::palette_capacity(data.bits_per_item()) <= palette.next_index();
+
+            let new_bits_per_item: Option<u8> = if existing_index.is_none() && is_full {
+                Self::grown_bits_per_item(
+                    self.growth_policy,
+                    self.min_bits_per_item,
+                    self.max_bits_per_item,
+                    self.direct_threshold,
+                    data.bits_per_item()
+                )
+            } else {
+                Some(data.bits_per_item())
+            };
+
+            if let Some(new_bits_per_item) = new_bits_per_item {
+                if new_bits_per_item != data.bits_per_item() {
+                    *data = data.repacked(Self::VOLUME, new_bits_per_item, |index| index);
+                }
+
+                let palette_index: usize = existing_index.unwrap_or_else(|| palette.insert(item));
+                let region_volume: usize = (max - min + IVec3::ONE).element_product() as usize;
+                palette.acquire_many(palette_index, region_volume);
+
+                for (start, len) in Self::region_runs(min, max) {
+                    unsafe {
+                        data.fill_unchecked(start, len, palette_index as u64);
+                    }
+                }
+
+                if self.compaction_policy == CompactionPolicy::WhenSparse && self.is_sparse() {
+                    self.compact();
+                }
+                return;
+            }
+
+            self.make_direct();
+        }
+
+        if let Storage::Direct(data) = Arc::make_mut(&mut self.storage) {
+            for (start, len) in Self::region_runs(min, max) {
+                data[start..start + len].fill(item.clone());
+            }
+        }
+    }
+
+    /// Replaces every item equal to `old` with `new` and returns how many were replaced.
+    ///
+    /// Only the palette entry is rewritten, unless `new` is already in the palette
+    /// and the two entries have to be merged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<&str, 16, 16, 16> = Section::filled("snow", 1);
+    /// section.set_item(IVec3::new(0, 0, 0), "stone").unwrap();
+    ///
+    /// assert_eq!(section.replace(&"snow", "grass"), 4095);
+    /// assert_eq!(section.count_of(&"grass"), 4095);
+    /// assert_eq!(section.count_of(&"snow"), 0);
+    /// assert_eq!(section.palette_len(), 2);
+    /// ```
+    pub fn replace(&mut self, old: &T, new: T) -> usize {
+        self.tracked(|section| section.replace_value(old, new))
+    }
+
+    // replaces every item equal to `old` without recording changes
+    fn replace_value(&mut self, old: &T, new: T) -> usize {
+        // nothing to replace leaves storage shared with a snapshot untouched
+        if old == &new || self.count_of(old) == 0 {
+            return 0;
+        }
+
+        let replaced: usize = match Arc::make_mut(&mut self.storage) {
+            Storage::Uniform(uniform) => {
+                if uniform != old {
+                    return 0;
+                }
+                *uniform = new;
+                Self::VOLUME
+            }
+            Storage::Indirect { palette, data } => {
+                let Some(old_index) = palette.index_of(old) else {
+                    return 0;
+                };
+                let count: usize = palette.count_of(old);
+                Self::replace_entry(palette, data, old_index, new);
+                count
+            }
+            Storage::Direct(data) => {
+                let mut count: usize = 0;
+                for item in data.iter_mut().filter(|item| *item == old) {
+                    *item = new.clone();
+                    count += 1;
+                }
+                count
+            }
+        };
+
+        if self.compaction_policy == CompactionPolicy::WhenSparse && self.is_sparse() {
+            self.compact();
+        }
+
+        replaced
+    }
+
+    /// Replaces every item equal to `old` in the box between two inclusive corners with `new`,
+    /// and returns how many were replaced.
+    /// Returns an error if either corner is out of the section bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::filled(1, 2);
+    /// let replaced: usize = section
+    ///     .replace_in_region(IVec3::new(0, 0, 0), IVec3::new(15, 0, 15), &1, 2)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(replaced, 256);
+    /// assert_eq!(*section.item(IVec3::new(4, 0, 4)).unwrap(), 2);
+    /// assert_eq!(*section.item(IVec3::new(4, 1, 4)).unwrap(), 1);
+    /// ```
+    pub fn replace_in_region(
+        &mut self,
+        min: IVec3,
+        max: IVec3,
+        old: &T,
+        new: T
+    ) -> Result<usize, BoundsError> {
+        Self::check_position_in_bounds(min)?;
+        Self::check_position_in_bounds(max)?;
+        if min.cmpgt(max).any() {
+            return Ok(0);
+        }
+
+        let matches: Vec<usize> = Self::region_runs(min, max)
+            .flat_map(|(start, len)| start..start + len)
+            .filter(|&item_index| unsafe { self.item_at(item_index) } == old)
+            .collect();
+
+        if old == &new || matches.is_empty() {
+            return Ok(0);
+        }
+        if matches.len() == self.count_of(old) {
+            return Ok(self.replace(old, new));
+        }
+
+        for &item_index in &matches {
+            unsafe {
+                self.set_item_at(item_index, new.clone());
+            }
+        }
+
+        Ok(matches.len())
+    }
+
+    /// Replaces every item matching the predicate with `new` and returns how many were replaced.
+    ///
+    /// The predicate is called once per distinct value rather than once per item,
+    /// unless the section is direct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(2);
+    /// for x in 0..16 {
+    ///     section.set_item(IVec3::new(x, 0, 0), x as u64).unwrap();
+    /// }
+    ///
+    /// assert_eq!(section.replace_where(|&item| item >= 8, 8), 7);
+    /// assert_eq!(section.count_of(&8), 8);
+    /// assert_eq!(*section.item(IVec3::new(12, 0, 0)).unwrap(), 8);
+    /// ```
+    pub fn replace_where(&mut self, mut predicate: impl FnMut(&T) -> bool, new: T) -> usize {
+        self.tracked(|section| {
+            if
+                matches!(*section.storage, Storage::Direct(_)) &&
+                let Storage::Direct(data) = Arc::make_mut(&mut section.storage)
+            {
+                let mut count: usize = 0;
+                for item in data.iter_mut().filter(|item| **item != new && predicate(item)) {
+                    *item = new.clone();
+                    count += 1;
+                }
+                return count;
+            }
+
+            let olds: Vec<T> = match &*section.storage {
+                Storage::Uniform(uniform) => vec![uniform.clone()],
+                Storage::Indirect { palette, .. } => {
+                    palette
+                        .usage()
+                        .map(|(item, _)| item.clone())
+                        .collect()
+                }
+                Storage::Direct(_) => unreachable!("direct items were replaced above"),
+            };
+
+            olds.into_iter()
+                .filter(|item| item != &new && predicate(item))
+                .map(|item| section.replace_value(&item, new.clone()))
+                .sum()
+        })
+    }
+
+    /// Replaces every item with the items of `source`, keeping this section's settings.
+    /// Returns an error if the dimensions differ or the items cannot be allocated.
+    ///
+    /// The copied items are packed with at least this section's minimum bits per item,
+    /// and stored directly if that is past its direct threshold.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::{ Error, Section };
+    ///
+    /// let mut source: Section<u64, 4, 4, 4> = Section::new(1);
+    /// source.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+    ///
+    /// let mut section: Section<u64, 4, 4, 4> = Section::new(1);
+    /// section.copy_from(&source).unwrap();
+    /// assert_eq!(*section.item(IVec3::new(1, 2, 3)).unwrap(), 5);
+    ///
+    /// let mut larger: Section<u64, 8, 8, 8> = Section::new(1);
+    /// assert!(matches!(larger.copy_from(&source), Err(Error::DimensionMismatch { .. })));
+    /// ```
+    pub fn copy_from<const SW: usize, const SH: usize, const SD: usize>(
+        &mut self,
+        source: &Section<T, SW, SH, SD>
+    ) -> Result<(), Error> {
+        if source.dimensions() != self.dimensions() {
+            return Err(Error::DimensionMismatch {
+                expected: self.dimensions(),
+                found: source.dimensions(),
+            });
+        }
+
+        let storage: Storage<T> = match &*source.storage {
+            Storage::Uniform(uniform) => Storage::Uniform(uniform.clone()),
+            Storage::Indirect { palette, data } => {
+                let mut data: PackedArray = data.try_copy(Self::VOLUME, self.layout)?;
+                if data.bits_per_item() < self.min_bits_per_item {
+                    data = data.repacked(Self::VOLUME, self.min_bits_per_item, |index| index);
+                }
+                Storage::Indirect {
+                    palette: palette.try_clone()?,
+                    data,
+                }
+            }
+            Storage::Direct(data) => {
+                let mut copy: Vec<T> = Vec::new();
+                copy.try_reserve_exact(Self::VOLUME)?;
+                copy.extend_from_slice(data);
+                Storage::Direct(copy)
+            }
+        };
+        self.tracked(|section| {
+            section.storage = Arc::new(storage);
+            section.apply_direct_threshold();
+        });
+
+        Ok(())
+    }
+
+    /// Copies the box of `size` items starting at `source_min` in `source`
+    /// to the box starting at `min` in this section.
+    /// Returns an error if either box is not within its section's bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let source: Section<u64, 4, 4, 4> = Section::filled(3, 1);
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+    ///
+    /// section.copy_region(&source, IVec3::ZERO, IVec3::splat(8), IVec3::splat(2)).unwrap();
+    /// assert_eq!(section.count_of(&3), 8);
+    /// assert_eq!(*section.item(IVec3::new(9, 9, 9)).unwrap(), 3);
+    /// ```
+    pub fn copy_region<const SW: usize, const SH: usize, const SD: usize>(
+        &mut self,
+        source: &Section<T, SW, SH, SD>,
+        source_min: IVec3,
+        min: IVec3,
+        size: IVec3
+    ) -> Result<(), Error> {
+        if size.cmple(IVec3::ZERO).any() {
+            return Ok(());
+        }
+
+        Section::<T, SW, SH, SD>::check_position_in_bounds(source_min)?;
+        Section::<T, SW, SH, SD>::check_position_in_bounds(source_min + size - IVec3::ONE)?;
+        Self::check_position_in_bounds(min)?;
+        Self::check_position_in_bounds(min + size - IVec3::ONE)?;
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    let offset: IVec3 = IVec3::new(x, y, z);
+                    let source_index: usize = Section::<T, SW, SH, SD>::item_index(
+                        source_min + offset
+                    );
+                    let item: T = unsafe { source.item_at(source_index) }.clone();
+                    unsafe {
+                        self.set_item_at(Self::item_index(min + offset), item);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets every item in the section to the value returned for its position.
+    ///
+    /// Every value is gathered first so the storage is rebuilt once,
+    /// with the fewest bits per item the distinct values need.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+    /// section.fill_with(|pos| if pos.y < 4 { 1 } else { 0 });
+    ///
+    /// assert_eq!(section.count_of(&1), 16 * 4 * 16);
+    /// assert_eq!(section.bits_per_item(), 1);
+    /// ```
+    pub fn fill_with(&mut self, f: impl FnMut(IVec3) -> T) {
+        let items: Vec<T> = Self::positions().map(f).collect();
+        let storage: Storage<T> = match self.compacted_direct(&items) {
+            Some(storage) => storage,
+            None => Storage::Direct(items),
+        };
+
+        self.tracked(|section| {
+            section.storage = Arc::new(storage);
+        });
+    }
+
+    /// Removes palette entries no longer referenced by any item
+    /// and shrinks the bits per item to the minimum the remaining entries need.
+    ///
+    /// A section left with a single item becomes uniform and releases its packed data,
+    /// and a direct section returns to a palette if the remaining items fit under the threshold.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(0);
+    ///
+    /// for x in 0..8 {
+    ///     section.set_item(IVec3::new(x, 0, 0), (x as u64) + 1).unwrap();
+    /// }
+    /// assert_eq!(section.bits_per_item(), 4);
+    ///
+    /// for x in 1..8 {
+    ///     section.set_item(IVec3::new(x, 0, 0), 0).unwrap();
+    /// }
+    /// section.compact();
+    /// assert_eq!(section.palette_len(), 2);
+    /// assert_eq!(section.bits_per_item(), 1);
+    /// assert_eq!(section.item(IVec3::new(0, 0, 0)).unwrap(), &1);
+    /// ```
+    pub fn compact(&mut self) {
+        if let Storage::Direct(data) = &*self.storage {
+            if let Some(storage) = self.compacted_direct(data) {
+                self.storage = Arc::new(storage);
+            }
+            return;
+        }
+
+        let Storage::Indirect { palette, data } = &*self.storage else {
+            return;
+        };
+
+        if palette.live_len() == 1 {
+            let (item, _) = palette.usage().next().expect("one entry is live");
+            self.storage = Arc::new(Storage::Uniform(item.clone()));
+            return;
+        }
+
+        let new_bits_per_item: u8 = Self::bits_needed(palette.live_len()).max(
+            self.min_bits_per_item
+        );
+        if palette.live_len() == palette.len() && new_bits_per_item == data.bits_per_item() {
+            return;
+        }
+
+        // only copied out of a snapshot once there is something to repack
+        let Storage::Indirect { palette, data } = Arc::make_mut(&mut self.storage) else {
+            unreachable!("storage was checked to be packed");
+        };
+        let remap: Vec<usize> = palette.remove_free();
+        *data = data.repacked(Self::VOLUME, new_bits_per_item, |palette_index| {
+            remap[palette_index as usize] as u64
+        });
+    }
+
+    /// Compacts the section to the fewest bits its live items need and releases spare capacity.
+    /// Returns how many bytes of heap memory were saved.
+    ///
+    /// Useful to run when a section is unloaded or saved.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(0);
+    ///
+    /// for x in 0..16 {
+    ///     section.set_item(IVec3::new(x, 0, 0), x as u64).unwrap();
+    /// }
+    /// for x in 2..16 {
+    ///     section.set_item(IVec3::new(x, 0, 0), 1).unwrap();
+    /// }
+    /// assert_eq!(section.bits_per_item(), 4);
+    ///
+    /// let saved: usize = section.shrink_to_fit();
+    /// assert!(saved >= 3 * 4096 / 8);
+    /// assert_eq!(section.bits_per_item(), 1);
+    /// ```
+    pub fn shrink_to_fit(&mut self) -> usize {
+        let heap_size: usize = self.heap_size();
+
+        self.compact();
+
+        // storage shared with a snapshot is left as it is
+        match Arc::get_mut(&mut self.storage) {
+            None | Some(Storage::Uniform(_)) => (),
+            Some(Storage::Indirect { palette, .. }) => palette.shrink_to_fit(),
+            Some(Storage::Direct(data)) => data.shrink_to_fit(),
+        }
+
+        heap_size.saturating_sub(self.heap_size())
+    }
+
+    /// Returns a copy of the section in constant time.
+    ///
+    /// The copy shares its storage with the section, and whichever of them is changed first
+    /// copies the storage before writing to it, so a snapshot can be handed to another thread
+    /// while the section keeps being edited. Cloning shares storage the same way,
+    /// but unlike a clone the snapshot does not track changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(4);
+    /// section.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+    ///
+    /// let snapshot: Section<u64, 16, 16, 16> = section.snapshot();
+    /// let saver = std::thread::spawn(move || snapshot.count_of(&5));
+    ///
+    /// section.set_item(IVec3::new(1, 2, 3), 6).unwrap();
+    /// assert_eq!(saver.join().unwrap(), 1);
+    /// assert_eq!(section.count_of(&5), 0);
+    /// ```
+    pub fn snapshot(&self) -> Self {
+        Self {
+            storage: Arc::clone(&self.storage),
+            initial_bits_per_item: self.initial_bits_per_item,
+            compaction_policy: self.compaction_policy,
+            direct_threshold: self.direct_threshold,
+            growth_policy: self.growth_policy,
+            min_bits_per_item: self.min_bits_per_item,
+            max_bits_per_item: self.max_bits_per_item,
+            layout: self.layout,
+            changes: None,
+        }
+    }
+
+    /// Returns a hash of the section's dimensions and items,
+    /// the same for any two sections that compare equal.
+    ///
+    /// Each distinct value is hashed once, then only its hash is mixed in for every item,
+    /// so the hash is cheap enough to deduplicate identical sections in storage.
+    /// It is stable within one build, but may change with the Rust version.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::Section;
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+    /// let mut other: Section<u64, 16, 16, 16> = Section::new(8);
+    /// section.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+    /// other.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+    ///
+    /// assert_eq!(section.content_hash(), other.content_hash());
+    /// assert!(section == other);
+    /// ```
+    pub fn content_hash(&self) -> u64 {
+        let hash_item = |item: &T| {
+            let mut hasher: DefaultHasher = DefaultHasher::new();
+            item.hash(&mut hasher);
+            hasher.finish()
+        };
+        let mut content_hash: u64 = [W, H, D].into_iter().fold(0, |hash, len| {
+            Self::mix_hash(hash, len as u64)
+        });
+
+        match &*self.storage {
+            Storage::Uniform(item) => {
+                let item_hash: u64 = hash_item(item);
+                for _ in 0..Self::VOLUME {
+                    content_hash = Self::mix_hash(content_hash, item_hash);
+                }
+            }
+            Storage::Indirect { palette, data } => {
+                let item_hashes: Vec<u64> = (0..palette.len())
+                    .map(|palette_index| hash_item(unsafe { palette.get_unchecked(palette_index) }))
+                    .collect();
+                for palette_index in data.iter(Self::VOLUME) {
+                    let item_hash: u64 = item_hashes[palette_index as usize];
+                    content_hash = Self::mix_hash(content_hash, item_hash);
+                }
+            }
+            Storage::Direct(data) => {
+                for item in data {
+                    content_hash = Self::mix_hash(content_hash, hash_item(item));
+                }
+            }
+        }
+
+        content_hash
+    }
+
+    // folds one more value into a running hash
+    #[inline]
+    const fn mix_hash(hash: u64, value: u64) -> u64 {
+        (hash.rotate_left(5) ^ value).wrapping_mul(0x517c_c1b7_2722_0a95)
+    }
+
+    /// Returns the bytes the section has allocated on the heap.
+    ///
+    /// Memory owned by the items themselves, such as the contents of a `String`, is not included.
+    /// Storage shared with snapshots is counted in full by every section sharing it.
+    pub fn heap_size(&self) -> usize {
+        match &*self.storage {
+            Storage::Uniform(_) => 0,
+            Storage::Indirect { palette, data } => palette.heap_size() + data.heap_size(),
+            Storage::Direct(data) => data.capacity() * size_of::<T>(),
+        }
+    }
+
+    // switches uniform storage to a palette holding its value
+    fn promote(&mut self) {
+        let Storage::Uniform(uniform) = &*self.storage else {
+            return;
+        };
+        let bits_per_item: u8 = self.initial_bits_per_item.max(self.min_bits_per_item).max(1);
+
+        self.storage = Arc::new(Storage::Indirect {
+            palette: Palette::new(
+                uniform.clone(),
+                Self::VOLUME,
+                Self::palette_capacity(bits_per_item).min(Self::VOLUME)
+            ),
+            data: PackedArray::new(Self::VOLUME, bits_per_item, self.layout),
+        });
+
+        self.apply_direct_threshold();
+    }
+
+    // drops the palette if the bits per item have outgrown the direct threshold
+    fn apply_direct_threshold(&mut self) {
+        if let Some(direct_threshold) = self.direct_threshold
+            && let Storage::Indirect { data, .. } = &*self.storage
+            && data.bits_per_item() > direct_threshold
+        {
+            self.make_direct();
+        }
+    }
+
+    // replaces indirect storage with every item stored whole
+    fn make_direct(&mut self) {
+        let Storage::Indirect { palette, data } = &*self.storage else {
+            return;
+        };
+
+        let direct: Vec<T> = (0..Self::VOLUME)
+            .map(|item_index| unsafe { palette.get_unchecked(data.get(item_index) as usize) })
+            .cloned()
+            .collect();
+        self.storage = Arc::new(Storage::Direct(direct));
+    }
+
+    // smaller storage able to hold the items of a direct section, if there is one
+    fn compacted_direct(&self, data: &[T]) -> Option<Storage<T>> {
+        let usage: Vec<(&T, usize)> = Self::direct_usage(data);
+
+        if usage.len() == 1 {
+            return Some(Storage::Uniform(usage[0].0.clone()));
+        }
+
+        let bits_per_item: u8 = Self::bits_needed(usage.len()).max(self.min_bits_per_item);
+        if self.direct_threshold.is_some_and(|max| bits_per_item > max) {
+            return None;
+        }
+
+        let indices: HashMap<&T, usize> = usage
+            .iter()
+            .enumerate()
+            .map(|(palette_index, &(item, _))| (item, palette_index))
+            .collect();
+
+        let mut packed: PackedArray = PackedArray::new(Self::VOLUME, bits_per_item, self.layout);
+        for (item_index, item) in data.iter().enumerate() {
+            unsafe {
+                packed.set_unchecked(item_index, indices[item] as u64);
+            }
+        }
+
+        let usage: Vec<(T, usize)> = usage
+            .into_iter()
+            .map(|(item, count)| (item.clone(), count))
+            .collect();
+
+        Some(Storage::Indirect {
+            palette: Palette::from_usage(usage),
+            data: packed,
+        })
+    }
+
+    // counts every distinct item of a direct section, in order of first occurrence
+    // so the same items always give the same palette
+    fn direct_usage(data: &[T]) -> Vec<(&T, usize)> {
+        let mut usage: Vec<(&T, usize)> = Vec::new();
+        let mut indices: HashMap<&T, usize> = HashMap::new();
+        for item in data {
+            let palette_index: usize = *indices.entry(item).or_insert_with(|| {
+                usage.push((item, 0));
+                usage.len() - 1
+            });
+            usage[palette_index].1 += 1;
+        }
+        usage
+    }
+
+    // whether the live palette entries fit in a quarter of the current capacity
+    // and compacting would actually lower the bits per item
+    #[inline]
+    fn is_sparse(&self) -> bool {
+        let bits_per_item: u8 = self.bits_per_item();
+        let live_len: usize = match &*self.storage {
+            Storage::Uniform(_) | Storage::Direct(_) => return false,
+            Storage::Indirect { palette, .. } => palette.live_len(),
+        };
+
+        bits_per_item >= 2 &&
+            live_len <= 1 << (bits_per_item - 2) &&
+            (live_len == 1 || self.min_bits_per_item < bits_per_item)
+    }
+
+    // bits per item once a full palette of the given width grows, or none if it should go direct
+    fn grown_bits_per_item(
+        growth_policy: GrowthPolicy,
+        min_bits_per_item: u8,
+        max_bits_per_item: u8,
+        direct_threshold: Option<u8>,
+        bits_per_item: u8
+    ) -> Option<u8> {
+        let required: u8 = bits_per_item + 1;
+        let grown: u8 = growth_policy
+            .grow(bits_per_item, required)
+            .max(min_bits_per_item)
+            .min(max_bits_per_item.max(required))
+            .min(64);
+
+        match direct_threshold {
+            Some(direct_threshold) if required > direct_threshold => None,
+            Some(direct_threshold) => Some(grown.min(direct_threshold)),
+            None => Some(grown),
+        }
+    }
+
+    // palette entries that indices of a width can address
+    #[inline]
+    const fn palette_capacity(bits_per_item: u8) -> usize {
+        // 64 bit indices address more entries than a usize can count
+        match 1usize.checked_shl(bits_per_item as u32) {
+            Some(capacity) => capacity,
+            None => usize::MAX,
+        }
+    }
+
+    // minimum bits per item able to index a palette of the given length
+    #[inline]
+    const fn bits_needed(palette_len: usize) -> u8 {
+        if palette_len <= 1 { 0 } else { (usize::BITS - (palette_len - 1).leading_zeros()) as u8 }
+    }
+
+    // runs a bulk edit, then records every item it changed if changes are tracked
+    fn tracked<R>(&mut self, edit: impl FnOnce(&mut Self) -> R) -> R {
+        // taken for the edit so nested edits do not record the same changes again
+        let Some(mut changes) = self.changes.take() else {
+            return edit(self);
+        };
+
+        let old: Vec<T> = self.values().cloned().collect();
+        let result: R = edit(self);
+
+        for (item_index, (old, new)) in old.into_iter().zip(self.values()).enumerate() {
+            if &old != new {
+                changes.record(item_index, Self::region_index(item_index), old, new.clone());
+            }
+        }
+
+        self.changes = Some(changes);
+        result
+    }
+
+    // index of the dirty region holding an item
+    #[inline]
+    fn region_index(item_index: usize) -> usize {
+        let region: IVec3 = Self::position_of(item_index) / (Self::DIRTY_REGION_SIZE as i32);
+        let regions: IVec3 = Self::regions();
+
+        (region.x * regions.y * regions.z + region.y * regions.z + region.z) as usize
+    }
+
+    // number of dirty regions along each axis
+    #[inline]
+    const fn regions() -> IVec3 {
+        let size: usize = Self::DIRTY_REGION_SIZE;
+        IVec3::new(W.div_ceil(size) as i32, H.div_ceil(size) as i32, D.div_ceil(size) as i32)
+    }
+
+    // moves every reference of a live palette entry onto another item,
+    // rewriting the entry in place unless the item already has one to merge into
+    fn replace_entry(palette: &mut Palette<T>, data: &mut PackedArray, old_index: usize, item: T) {
+        let Some(new_index) = palette.index_of(&item) else {
+            palette.set_entry(old_index, item);
+            return;
+        };
+
+        let count: usize = palette.count_of(unsafe { palette.get_unchecked(old_index) });
+        for item_index in 0..Self::VOLUME {
+            if data.get(item_index) as usize == old_index {
+                unsafe {
+                    data.set_unchecked(item_index, new_index as u64);
+                }
+            }
+        }
+
+        palette.release_many(old_index, count);
+        palette.acquire_many(new_index, count);
+    }
+
+    // consecutive runs of item indices covering the box between two inclusive corners,
+    // merging rows and slices that span the whole section
+    fn region_runs(min: IVec3, max: IVec3) -> impl Iterator<Item = (usize, usize)> {
+        let size: IVec3 = max - min + IVec3::ONE;
+        let full_z: bool = size.z as usize == D;
+        let full_yz: bool = full_z && size.y as usize == H;
+
+        let (x_range, y_range, len) = if full_yz {
+            (min.x..=min.x, min.y..=min.y, (size.x as usize) * H * D)
+        } else if full_z {
+            (min.x..=max.x, min.y..=min.y, (size.y as usize) * D)
+        } else {
+            (min.x..=max.x, min.y..=max.y, size.z as usize)
+        };
+
+        x_range.flat_map(move |x| {
+            y_range
+                .clone()
+                .map(move |y| (Self::item_index(IVec3::new(x, y, min.z)), len))
+        })
+    }
+
+    // every position in item index order
+    fn positions() -> impl Iterator<Item = IVec3> {
+        (0..W as i32).flat_map(|x| {
+            (0..H as i32).flat_map(move |y| (0..D as i32).map(move |z| IVec3::new(x, y, z)))
+        })
+    }
+
+    #[inline]
+    const fn position_of(item_index: usize) -> IVec3 {
+        IVec3::new(
+            (item_index / (H * D)) as i32,
+            ((item_index / D) % H) as i32,
+            (item_index % D) as i32
+        )
+    }
+
+    #[inline]
+    const fn item_index(pos: IVec3) -> usize {
+        (pos.x as usize) * (H * D) + (pos.y as usize) * D + (pos.z as usize)
+    }
+
+    const fn check_position_in_bounds(pos: IVec3) -> Result<(), BoundsError> {
+        if
+            pos.x < 0 ||
+            pos.x >= (W as i32) ||
+            pos.y < 0 ||
+            pos.y >= (H as i32) ||
+            pos.z < 0 ||
+            pos.z >= (D as i32)
+        {
+            Err(BoundsError::OutOfBounds(pos))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Sections are equal when every item is, whatever their storage, palette order,
+/// bits per item or settings.
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> PartialEq
+for Section<T, W, H, D> {
+    fn eq(&self, other: &Self) -> bool {
+        match (&*self.storage, &*other.storage) {
+            _ if Arc::ptr_eq(&self.storage, &other.storage) => true,
+            (Storage::Uniform(item), Storage::Uniform(other_item)) => item == other_item,
+            _ => self.values().eq(other.values()),
+        }
+    }
+}
+
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Eq for Section<T, W, H, D> {}
+
+/// Hashes the [`Section::content_hash`], so equal sections hash the same.
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Hash for Section<T, W, H, D> {
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        state.write_u64(self.content_hash());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_is_empty() {
+        let section: Section<u64, 16, 16, 16> = Section::new(2);
+        assert!(section.is_empty());
+    }
+
+    #[test]
+    fn test_set_and_get_item() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(4);
+        let pos_1: IVec3 = IVec3::new(15, 1, 1);
+        let pos_2: IVec3 = IVec3::new(15, 1, 2);
+
+        unsafe {
+            section.set_item_unchecked(pos_1, 3);
+            section.set_item_unchecked(pos_1, 2);
+            section.set_item_unchecked(pos_2, 1);
+
+            assert_eq!(*section.item_unchecked(pos_1), 2);
+            assert_eq!(*section.item_unchecked(pos_2), 1);
+        }
+    }
+
+    #[test]
+    fn test_repack() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(1);
+        let pos: IVec3 = IVec3::new(3, 5, 3);
+
+        unsafe {
+            section.set_item_unchecked(pos, 30);
+            assert_eq!(*section.item_unchecked(pos), 30);
+        }
+    }
+
+    #[test]
+    fn test_max_fill() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(0);
+
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let pos: IVec3 = IVec3::new(x, y, z);
+                    section.set_item(pos, (x + y + z) as u64).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(0);
+
+        for x in 0..16 {
+            let pos: IVec3 = IVec3::new(x, 0, 0);
+            section.set_item(pos, (x as u64) + 100).unwrap();
+        }
+        for x in 0..16 {
+            let pos: IVec3 = IVec3::new(x, 0, 0);
+            section.set_item(pos, (x as u64) % 3).unwrap();
+        }
+        assert_eq!(section.bits_per_item(), 5);
+
+        section.compact();
+        assert_eq!(section.palette_len(), 3);
+        assert_eq!(section.bits_per_item(), 2);
+
+        for x in 0..16 {
+            let pos: IVec3 = IVec3::new(x, 0, 0);
+            assert_eq!(*section.item(pos).unwrap(), (x as u64) % 3);
+        }
+        assert_eq!(*section.item(IVec3::new(0, 1, 0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_freed_slots_are_reused() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(2);
+        let pos: IVec3 = IVec3::new(7, 7, 7);
+
+        for item in 1..1000 {
+            section.set_item(pos, item).unwrap();
+            assert_eq!(*section.item(pos).unwrap(), item);
+        }
+        assert_eq!(section.palette_len(), 2);
+        assert_eq!(section.bits_per_item(), 2);
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(0);
+
+        for x in 0..4 {
+            for y in 0..4 {
+                section.set_item(IVec3::new(x, y, 0), 5).unwrap();
+            }
+        }
+        assert_eq!(section.count_of(&5), 16);
+        assert_eq!(section.count_of(&0), 48);
+
+        section.set_item(IVec3::new(0, 0, 0), 6).unwrap();
+        section.set_item(IVec3::new(0, 0, 1), 6).unwrap();
+        assert_eq!(section.count_of(&5), 15);
+        assert_eq!(section.count_of(&6), 2);
+        assert_eq!(section.count_of(&0), 47);
+
+        let mut usage: Vec<(u64, usize)> = section
+            .palette_usage()
+            .map(|(&item, count)| (item, count))
+            .collect();
+        usage.sort();
+        assert_eq!(usage, [(0, 47), (5, 15), (6, 2)]);
+    }
+
+    #[test]
+    fn test_high_variety() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(0);
+
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let pos: IVec3 = IVec3::new(x, y, z);
+                    section.set_item(pos, ((x * 256 + y * 16 + z) as u64) * 7).unwrap();
+                }
+            }
+        }
+        for x in 0..16 {
+            for y in 0..16 {
+                let pos: IVec3 = IVec3::new(x, y, 0);
+                section.set_item(pos, 1).unwrap();
+            }
+        }
+
+        assert_eq!(section.count_of(&1), 256);
+        assert_eq!(section.count_of(&7), 1);
+        assert_eq!(section.count_of(&0), 0);
+        assert_eq!(*section.item(IVec3::new(15, 15, 15)).unwrap(), 4095 * 7);
+
+        section.compact();
+        assert_eq!(section.palette_len(), 4096 - 256 + 1);
+        assert_eq!(section.count_of(&(16 * 7 + 7)), 1);
+        assert_eq!(*section.item(IVec3::new(3, 2, 1)).unwrap(), (3 * 256 + 2 * 16 + 1) * 7);
+    }
+
+    #[test]
+    fn test_uniform_promote_and_demote() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(3);
+        let pos: IVec3 = IVec3::new(4, 5, 6);
+        assert!(section.is_uniform());
+        assert_eq!(section.bits_per_item(), 0);
+
+        section.set_item(pos, 0).unwrap();
+        assert!(matches!(*section.storage, Storage::Uniform(0)));
+
+        section.set_item(pos, 8).unwrap();
+        assert!(!section.is_uniform());
+        assert_eq!(section.bits_per_item(), 3);
+        assert_eq!(*section.item(pos).unwrap(), 8);
+        assert_eq!(*section.item(IVec3::new(0, 0, 0)).unwrap(), 0);
+
+        section.set_item(pos, 0).unwrap();
+        assert!(section.is_uniform());
+        assert!(matches!(*section.storage, Storage::Indirect { .. }));
+
+        section.compact();
+        assert!(matches!(*section.storage, Storage::Uniform(0)));
+        assert!(section.is_empty());
+
+        section.set_item(pos, 2).unwrap();
+        section.fill(2);
+        assert!(matches!(*section.storage, Storage::Uniform(2)));
+        assert_eq!(*section.item(IVec3::new(15, 15, 15)).unwrap(), 2);
+        assert!(!section.is_empty());
+    }
+
+    #[test]
+    fn test_direct() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(2);
+        section.set_direct_threshold(Some(3));
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let pos: IVec3 = IVec3::new(x, 3, z);
+                section.set_item(pos, u64::MAX - ((x * 16 + z) as u64)).unwrap();
+            }
+        }
+        assert!(section.is_direct());
+        assert_eq!(section.bits_per_item(), 0);
+        assert_eq!(section.palette_len(), 0);
+        assert_eq!(section.count_of(&u64::MAX), 1);
+        assert_eq!(section.count_of(&0), 4096 - 256);
+        assert_eq!(section.palette_usage().count(), 257);
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let pos: IVec3 = IVec3::new(x, 3, z);
+                assert_eq!(*section.item(pos).unwrap(), u64::MAX - ((x * 16 + z) as u64));
+                section.set_item(pos, (z % 4) as u64).unwrap();
+            }
+        }
+
+        section.compact();
+        assert!(!section.is_direct());
+        assert_eq!(section.bits_per_item(), 2);
+        assert_eq!(*section.item(IVec3::new(5, 3, 7)).unwrap(), 3);
+        assert_eq!(section.count_of(&3), 64);
+
+        section.set_direct_threshold(Some(1));
+        assert!(section.is_direct());
+        assert_eq!(*section.item(IVec3::new(5, 3, 6)).unwrap(), 2);
+
+        section.set_direct_threshold(None);
+        section.fill(7);
+        section.set_item(IVec3::new(0, 0, 0), 8).unwrap();
+        section.compact();
+        assert!(!section.is_direct());
+    }
+
+    #[test]
+    fn test_non_numeric_items() {
+        let mut section: Section<String, 8, 8, 8> = Section::filled("air".to_string(), 1);
+
+        for y in 0..4 {
+            section.set_item(IVec3::new(1, y, 1), "stone".to_string()).unwrap();
+        }
+        section.set_item(IVec3::new(1, 0, 1), "grass".to_string()).unwrap();
+
+        assert_eq!(section.item(IVec3::new(1, 0, 1)).unwrap(), "grass");
+        assert_eq!(section.item(IVec3::new(1, 3, 1)).unwrap(), "stone");
+        assert_eq!(section.item(IVec3::new(0, 0, 0)).unwrap(), "air");
+        assert_eq!(section.count_of(&"stone".to_string()), 3);
+        assert_eq!(section.count_of(&"air".to_string()), 512 - 4);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut section: Section<u64, 16, 16, 16> = Section::new(8);
+        assert_eq!(section.shrink_to_fit(), 0);
+
+        section.set_item(IVec3::new(0, 0, 0), 1).unwrap();
+        let heap_size: usize = section.heap_size();
+        let saved: usize = section.shrink_to_fit();
+
+        assert_eq!(section.bits_per_item(), 1);
+        assert_eq!(section.heap_size(), heap_size - saved);
+        assert!(saved > 7 * 4096 / 8);
+        assert_eq!(*section.item(IVec3::new(0, 0, 0)).unwrap(), 1);
+
+        section.set_item(IVec3::new(0, 0, 0), 0).unwrap();
+        section.shrink_to_fit();
+        assert_eq!(section.heap_size(), 0);
+    }
+
+    #[test]
+    fn test_growth_policy() {
+        let fill = |section: &mut Section<u64, 16, 16, 16>, count: u64| {
+            for item in 0..count {
+                let x: i32 = (item / 256) as i32;
+                let y: i32 = ((item / 16) % 16) as i32;
+                let z: i32 = (item % 16) as i32;
+                let pos: IVec3 = IVec3::new(x, y, z);
+                section.set_item(pos, item + 1).unwrap();
+            }
+        };
+
+        let mut section: Section<u64, 16, 16, 16> = SectionBuilder::new()
+            .bits_per_item(1)
+            .growth_policy(GrowthPolicy::Double)
+            .build();
+        fill(&mut section, 4);
+        assert_eq!(section.bits_per_item(), 4);
+        fill(&mut section, 16);
+        assert_eq!(section.bits_per_item(), 8);
+
+        let mut section: Section<u64, 16, 16, 16> = SectionBuilder::new()
+            .growth_policy(GrowthPolicy::Double)
+            .max_bits_per_item(6)
+            .build();
+        fill(&mut section, 300);
+        assert_eq!(section.bits_per_item(), 9);
+        assert_eq!(*section.item(IVec3::new(1, 2, 3)).unwrap(), 256 + 32 + 3 + 1);
+
+        let mut section: Section<u64, 16, 16, 16> = SectionBuilder::new()
+            .growth_policy(GrowthPolicy::ByteAligned)
+            .min_bits_per_item(4)
+            .direct_threshold(Some(11))
+            .build();
+        fill(&mut section, 2);
+        assert_eq!(section.bits_per_item(), 4);
+        fill(&mut section, 300);
+        assert_eq!(section.bits_per_item(), 11);
+        fill(&mut section, 4096);
+        assert!(section.is_direct());
+
+        section.fill(0);
+        fill(&mut section, 2);
+        section.compact();
+        assert_eq!(section.bits_per_item(), 4);
+    }
+
+    #[test]
+    fn test_aligned_layout() {
+        let mut spanning: Section<u64, 16, 16, 16> = Section::new(0);
+        let mut aligned: Section<u64, 16, 16, 16> = SectionBuilder::new()
+            .layout(Layout::Aligned)
+            .build();
+        assert_eq!(aligned.layout(), Layout::Aligned);
+
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let pos: IVec3 = IVec3::new(x, y, z);
+                    let item: u64 = ((x * y + z) % 37) as u64;
+                    spanning.set_item(pos, item).unwrap();
+                    aligned.set_item(pos, item).unwrap();
+                }
+            }
+        }
+        aligned.set_item(IVec3::new(3, 3, 3), 1000).unwrap();
+        spanning.set_item(IVec3::new(3, 3, 3), 1000).unwrap();
+
+        assert_eq!(aligned.bits_per_item(), spanning.bits_per_item());
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let pos: IVec3 = IVec3::new(x, y, z);
+                    assert_eq!(aligned.item(pos).unwrap(), spanning.item(pos).unwrap());
+                }
+            }
+        }
+
+        aligned.compact();
+        assert_eq!(*aligned.item(IVec3::new(3, 3, 3)).unwrap(), 1000);
+        assert_eq!(aligned.count_of(&0), spanning.count_of(&0));
+    }
+
+    #[test]
+    fn test_compact_when_sparse() {
+        let mut section: Section<u64, 4, 4, 4> = Section::with_compaction(
+            0,
+            CompactionPolicy::WhenSparse
+        );
+
+        for z in 0..4 {
+            section.set_item(IVec3::new(0, 0, z), (z as u64) + 1).unwrap();
+        }
+        assert_eq!(section.bits_per_item(), 3);
+
+        for z in 0..4 {
+            section.set_item(IVec3::new(0, 0, z), 0).unwrap();
+        }
+        assert_eq!(section.bits_per_item(), 1);
+        assert!(section.is_empty());
+    }
+
+    #[test]
+    fn test_iter_matches_item() {
+        let mut uniform: Section<u64, 4, 5, 6> = Section::filled(7, 1);
+        let mut indirect: Section<u64, 4, 5, 6> = Section::new(1);
+        let mut direct: Section<u64, 4, 5, 6> = SectionBuilder::new()
+            .direct_threshold(Some(2))
+            .build();
+        let mut aligned: Section<u64, 4, 5, 6> = SectionBuilder::new()
+            .layout(Layout::Aligned)
+            .build();
+
+        for x in 0..4 {
+            for y in 0..5 {
+                for z in 0..6 {
+                    let pos: IVec3 = IVec3::new(x, y, z);
+                    let item: u64 = ((x * 7 + y * 3 + z) % 11) as u64;
+                    indirect.set_item(pos, item).unwrap();
+                    direct.set_item(pos, item).unwrap();
+                    aligned.set_item(pos, item).unwrap();
+                }
+            }
+        }
+        assert_eq!(direct.bits_per_item(), 0);
+        uniform.set_item(IVec3::new(0, 0, 0), 7).unwrap();
+
+        for section in [&uniform, &indirect, &direct, &aligned] {
+            assert_eq!(section.values().len(), 120);
+
+            let mut expected_index: usize = 0;
+            for (item_index, (pos, item)) in section.iter().enumerate() {
+                assert_eq!(item_index, expected_index);
+                assert_eq!(section.item(pos).unwrap(), item);
+                expected_index += 1;
+            }
+            assert_eq!(expected_index, 120);
+        }
+    }
+
+    #[test]
+    fn test_iter_nonzero() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        assert_eq!(section.iter_nonzero().count(), 0);
+
+        section.set_item(IVec3::new(7, 0, 1), 3).unwrap();
+        section.set_item(IVec3::new(0, 2, 5), 9).unwrap();
+
+        let nonzero: Vec<(IVec3, u64)> = section
+            .iter_nonzero()
+            .map(|(pos, &item)| (pos, item))
+            .collect();
+        assert_eq!(nonzero, [(IVec3::new(0, 2, 5), 9), (IVec3::new(7, 0, 1), 3)]);
+    }
+
+    #[test]
+    fn test_for_each_mut() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(0);
+        section.for_each_mut(|_, item| *item += 1);
+        assert_eq!(section.count_of(&1), 64);
+        assert!(section.is_uniform());
+
+        section.for_each_mut(|pos, item| {
+            if pos.x == 3 {
+                *item = 5;
+            }
+        });
+        assert_eq!(section.count_of(&5), 16);
+        assert_eq!(section.count_of(&1), 48);
+        assert_eq!(*section.item(IVec3::new(3, 1, 2)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_fill_region() {
+        let regions: [(IVec3, IVec3); 4] = [
+            (IVec3::new(1, 2, 3), IVec3::new(6, 4, 7)),
+            (IVec3::new(0, 5, 0), IVec3::new(7, 6, 7)),
+            (IVec3::new(2, 0, 0), IVec3::new(3, 7, 7)),
+            (IVec3::new(4, 4, 4), IVec3::new(4, 4, 4)),
+        ];
+        let builders: [SectionBuilder; 3] = [
+            SectionBuilder::new().bits_per_item(1),
+            SectionBuilder::new().layout(Layout::Aligned),
+            SectionBuilder::new().direct_threshold(Some(2)),
+        ];
+
+        for builder in builders {
+            let mut filled: Section<u64, 8, 8, 8> = builder.build();
+            let mut expected: Section<u64, 8, 8, 8> = builder.build();
+
+            for (item, &(min, max)) in regions.iter().enumerate() {
+                let item: u64 = (item as u64) + 1;
+                filled.fill_region(min, max, item).unwrap();
+
+                for x in min.x..=max.x {
+                    for y in min.y..=max.y {
+                        for z in min.z..=max.z {
+                            expected.set_item(IVec3::new(x, y, z), item).unwrap();
+                        }
+                    }
+                }
+            }
+
+            assert!(filled.values().eq(expected.values()));
+            for item in 0..=4 {
+                assert_eq!(filled.count_of(&item), expected.count_of(&item));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_region_edges() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(2);
+
+        section.fill_region(IVec3::new(2, 0, 0), IVec3::new(1, 3, 3), 5).unwrap();
+        assert!(section.is_empty());
+
+        section.fill_region(IVec3::ZERO, IVec3::new(3, 3, 3), 5).unwrap();
+        assert!(matches!(*section.storage, Storage::Uniform(5)));
+
+        section.fill_region(IVec3::ZERO, IVec3::new(3, 3, 1), 5).unwrap();
+        assert!(matches!(*section.storage, Storage::Uniform(5)));
+
+        let mut section: Section<u64, 4, 4, 4> = Section::with_compaction(
+            2,
+            CompactionPolicy::WhenSparse
+        );
+        for item in 1..4 {
+            section.set_item(IVec3::new(0, 0, item as i32), item).unwrap();
+        }
+        section.fill_region(IVec3::ZERO, IVec3::new(0, 0, 3), 0).unwrap();
+        assert!(section.is_uniform());
+        assert!(section.is_empty());
+    }
+
+    #[test]
+    fn test_fill_with() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        section.fill_with(|pos| (pos.x / 2) as u64);
+        assert_eq!(section.bits_per_item(), 2);
+        assert_eq!(section.count_of(&3), 128);
+        assert_eq!(*section.item(IVec3::new(5, 1, 1)).unwrap(), 2);
+
+        section.fill_with(|_| 7);
+        assert!(matches!(*section.storage, Storage::Uniform(7)));
+
+        section.set_direct_threshold(Some(3));
+        section.fill_with(|pos| (pos.x + pos.y * 8) as u64);
+        assert!(section.is_direct());
+        assert_eq!(*section.item(IVec3::new(3, 2, 0)).unwrap(), 19);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(0);
+        section.fill_with(|pos| (pos.x * 8 + pos.y) as u64);
+        assert_eq!(section.palette_len(), 64);
+
+        assert_eq!(section.replace(&10, 100), 8);
+        assert_eq!(section.palette_len(), 64);
+        assert_eq!(section.count_of(&100), 8);
+        assert_eq!(*section.item(IVec3::new(1, 2, 5)).unwrap(), 100);
+
+        assert_eq!(section.replace(&11, 100), 8);
+        assert_eq!(section.count_of(&100), 16);
+        assert_eq!(section.count_of(&11), 0);
+        assert_eq!(*section.item(IVec3::new(1, 3, 0)).unwrap(), 100);
+
+        assert_eq!(section.replace(&11, 5), 0);
+        assert_eq!(section.replace(&100, 100), 0);
+        assert_eq!(section.count_of(&5), 8);
+
+        section.set_direct_threshold(Some(4));
+        section.set_item(IVec3::ZERO, 1000).unwrap();
+        assert!(section.is_direct());
+        assert_eq!(section.replace(&100, 5), 16);
+        assert_eq!(section.count_of(&5), 24);
+    }
+
+    #[test]
+    fn test_replace_in_region() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        section.fill_region(IVec3::ZERO, IVec3::new(7, 3, 7), 1).unwrap();
+
+        let replaced: usize = section
+            .replace_in_region(IVec3::new(0, 2, 0), IVec3::new(7, 5, 7), &1, 2)
+            .unwrap();
+        assert_eq!(replaced, 128);
+        assert_eq!(section.count_of(&1), 128);
+        assert_eq!(section.count_of(&2), 128);
+        assert_eq!(section.count_of(&0), 256);
+
+        let replaced: usize = section
+            .replace_in_region(IVec3::ZERO, IVec3::new(7, 7, 7), &2, 3)
+            .unwrap();
+        assert_eq!(replaced, 128);
+        assert_eq!(*section.item(IVec3::new(0, 3, 0)).unwrap(), 3);
+        assert!(section.replace_in_region(IVec3::ZERO, IVec3::splat(8), &3, 4).is_err());
+
+        let replaced: usize = section
+            .replace_in_region(IVec3::new(0, 0, 3), IVec3::new(3, 3, 1), &0, 5)
+            .unwrap();
+        assert_eq!(replaced, 0);
+        assert_eq!(section.count_of(&5), 0);
+    }
+
+    #[test]
+    fn test_replace_where() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(1);
+        section.fill_with(|pos| pos.z as u64);
+
+        assert_eq!(section.replace_where(|&item| item % 2 == 1, 0), 32);
+        assert_eq!(section.count_of(&0), 48);
+        assert_eq!(section.count_of(&2), 16);
+
+        assert_eq!(section.replace_where(|_| true, 9), 64);
+        assert_eq!(section.count_of(&9), 64);
+    }
+
+    #[test]
+    fn test_replace_counts_changed_items() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(1);
+        section.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+
+        let before: Section<u64, 4, 4, 4> = section.clone();
+        let replaced: usize = section.replace_where(|_| true, 5);
+        let changed: usize = before
+            .values()
+            .zip(section.values())
+            .filter(|(old, new)| old != new)
+            .count();
+        assert_eq!(replaced, 63);
+        assert_eq!(replaced, changed);
+        assert_eq!(section.count_of(&5), 64);
+
+        assert_eq!(section.replace(&5, 5), 0);
+        assert_eq!(section.replace_in_region(IVec3::ZERO, IVec3::splat(3), &5, 5).unwrap(), 0);
+
+        section.set_direct_threshold(Some(1));
+        section.fill_with(|pos| pos.x as u64);
+        assert!(section.is_direct());
+        assert_eq!(section.replace_where(|&item| item >= 2, 3), 16);
+        assert_eq!(section.count_of(&3), 32);
+    }
+
+    #[test]
+    fn test_copy_from() {
+        let mut source: Section<u64, 8, 8, 8> = SectionBuilder::new()
+            .layout(Layout::Aligned)
+            .build();
+        source.fill_with(|pos| ((pos.x + pos.y * pos.z) % 5) as u64);
+
+        let mut section: Section<u64, 8, 8, 8> = Section::filled(9, 2);
+        section.copy_from(&source).unwrap();
+        assert!(section.values().eq(source.values()));
+
+        section.set_item(IVec3::new(7, 7, 7), 100).unwrap();
+        assert_eq!(*section.item(IVec3::new(7, 7, 7)).unwrap(), 100);
+        assert_eq!(*source.item(IVec3::new(7, 7, 7)).unwrap(), 1);
+        assert_eq!(section.count_of(&0), source.count_of(&0));
+        assert_eq!(section.layout(), Layout::Spanning);
+    }
+
+    #[test]
+    fn test_copy_from_keeps_settings() {
+        let mut source: Section<u64, 4, 4, 4> = Section::new(1);
+        source.fill_with(|pos| (pos.x * 16 + pos.y * 4 + pos.z) as u64);
+        assert_eq!(source.bits_per_item(), 6);
+
+        let mut section: Section<u64, 4, 4, 4> = SectionBuilder::new()
+            .direct_threshold(Some(2))
+            .build();
+        section.copy_from(&source).unwrap();
+        assert!(section.is_direct());
+        assert!(section.values().eq(source.values()));
+
+        let mut source: Section<u64, 4, 4, 4> = Section::new(1);
+        source.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+
+        let mut section: Section<u64, 4, 4, 4> = SectionBuilder::new()
+            .min_bits_per_item(4)
+            .build();
+        section.copy_from(&source).unwrap();
+        assert_eq!(section.bits_per_item(), 4);
+        assert_eq!(*section.item(IVec3::new(1, 2, 3)).unwrap(), 5);
+        assert_eq!(section.count_of(&0), 63);
+    }
+
+    #[test]
+    fn test_copy_region() {
+        let mut source: Section<u64, 4, 4, 4> = Section::new(1);
+        source.fill_with(|pos| (pos.x * 16 + pos.y * 4 + pos.z) as u64);
+
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        section
+            .copy_region(&source, IVec3::new(1, 1, 1), IVec3::new(5, 0, 2), IVec3::splat(3))
+            .unwrap();
+        assert_eq!(section.count_of(&0), 512 - 27);
+        assert_eq!(*section.item(IVec3::new(5, 0, 2)).unwrap(), 21);
+        assert_eq!(*section.item(IVec3::new(7, 2, 4)).unwrap(), 63);
+
+        let result = section.copy_region(&source, IVec3::splat(2), IVec3::ZERO, IVec3::splat(3));
+        assert!(matches!(result, Err(Error::Bounds(_))));
+        let result = section.copy_region(&source, IVec3::ZERO, IVec3::splat(6), IVec3::splat(3));
+        assert!(matches!(result, Err(Error::Bounds(_))));
+    }
+
+    #[test]
+    fn test_changes_are_opt_in() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        section.set_item(IVec3::new(1, 2, 3), 4).unwrap();
+        assert!(!section.is_tracking_changes());
+        assert!(!section.is_changed());
+        assert!(section.take_changes().is_empty());
+
+        let section: Section<u64, 8, 8, 8> = SectionBuilder::new().track_changes(true).build();
+        assert!(section.is_tracking_changes());
+    }
+
+    #[test]
+    fn test_take_changes() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        section.set_track_changes(true);
+
+        section.set_item(IVec3::new(1, 2, 3), 4).unwrap();
+        section.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+        section.set_item(IVec3::new(7, 7, 7), 6).unwrap();
+        section.set_item(IVec3::new(7, 7, 7), 0).unwrap();
+        section.set_item(IVec3::new(0, 0, 0), 0).unwrap();
+        assert!(section.is_changed());
+        assert!(section.is_region_dirty(IVec3::new(0, 0, 0)));
+        assert!(section.is_region_dirty(IVec3::new(1, 1, 1)));
+        assert!(!section.is_region_dirty(IVec3::new(1, 0, 0)));
+        assert!(!section.is_region_dirty(IVec3::new(2, 0, 0)));
+
+        let changes: Vec<Change<u64>> = section.take_changes();
+        assert_eq!(changes, [Change { pos: IVec3::new(1, 2, 3), old: 0, new: 5 }]);
+        assert!(!section.is_changed());
+        assert_eq!(section.dirty_regions().count(), 0);
+    }
+
+    #[test]
+    fn test_bulk_changes() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        section.set_item(IVec3::new(0, 0, 0), 2).unwrap();
+        section.set_track_changes(true);
+
+        section.fill_region(IVec3::new(0, 0, 0), IVec3::new(0, 0, 1), 3).unwrap();
+        section.replace(&3, 4);
+        let changes: Vec<Change<u64>> = section.take_changes();
+        assert_eq!(changes, [
+            Change { pos: IVec3::new(0, 0, 0), old: 2, new: 4 },
+            Change { pos: IVec3::new(0, 0, 1), old: 0, new: 4 },
+        ]);
+
+        section.fill(7);
+        assert_eq!(section.take_changes().len(), 512);
+        assert_eq!(section.dirty_regions().count(), 0);
+
+        section.fill_region(IVec3::new(3, 3, 3), IVec3::new(4, 4, 4), 1).unwrap();
+        assert_eq!(section.dirty_regions().count(), 8);
+        assert_eq!(section.take_changes().len(), 8);
+    }
+
+    #[test]
+    fn test_snapshot_copy_on_write() {
+        let mut section: Section<u64, 8, 8, 8> = Section::new(2);
+        section.set_track_changes(true);
+        section.set_item(IVec3::new(1, 1, 1), 3).unwrap();
+
+        let snapshot: Section<u64, 8, 8, 8> = section.snapshot();
+        assert!(Arc::ptr_eq(&section.storage, &snapshot.storage));
+        assert!(!snapshot.is_tracking_changes());
+
+        section.set_item(IVec3::new(1, 1, 1), 3).unwrap();
+        section.replace(&9, 4);
+        assert!(Arc::ptr_eq(&section.storage, &snapshot.storage));
+
+        section.fill_region(IVec3::ZERO, IVec3::splat(3), 5).unwrap();
+        assert!(!Arc::ptr_eq(&section.storage, &snapshot.storage));
+        assert_eq!(section.count_of(&5), 64);
+        assert_eq!(snapshot.count_of(&5), 0);
+        assert_eq!(*snapshot.item(IVec3::new(1, 1, 1)).unwrap(), 3);
+
+        let mut clone: Section<u64, 8, 8, 8> = snapshot.clone();
+        clone.set_item(IVec3::new(1, 1, 1), 0).unwrap();
+        assert_eq!(*snapshot.item(IVec3::new(1, 1, 1)).unwrap(), 3);
+        assert!(clone.is_empty());
+    }
+
+    #[test]
+    fn test_eq_ignores_storage() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(4);
+        for z in 0..4 {
+            section.set_item(IVec3::new(0, 0, z), z as u64 + 10).unwrap();
+        }
+        section.fill_region(IVec3::ZERO, IVec3::new(0, 0, 2), 0).unwrap();
+
+        let mut other: Section<u64, 4, 4, 4> = SectionBuilder::new()
+            .bits_per_item(1)
+            .layout(Layout::Aligned)
+            .build();
+        other.set_item(IVec3::new(0, 0, 3), 13).unwrap();
+        assert!(section == other);
+        assert_eq!(section.content_hash(), other.content_hash());
+
+        let mut direct: Section<u64, 4, 4, 4> = Section::new(1);
+        direct.fill_with(|pos| if pos == IVec3::new(0, 0, 3) { 13 } else { 0 });
+        direct.set_direct_threshold(Some(0));
+        assert!(direct.is_direct());
+        assert!(direct == section);
+        assert_eq!(direct.content_hash(), section.content_hash());
+
+        other.set_item(IVec3::new(0, 0, 3), 0).unwrap();
+        assert!(section != other);
+        assert_ne!(section.content_hash(), other.content_hash());
+        assert!(other == Section::filled(0, 0));
+        assert_eq!(other.content_hash(), Section::<u64, 4, 4, 4>::filled(0, 0).content_hash());
+
+        let mut set: std::collections::HashSet<Section<u64, 4, 4, 4>> = Default::default();
+        set.insert(section);
+        assert!(!set.insert(direct));
+    }
+
+    #[test]
+    fn test_palette_capacity_is_bounded_by_volume() {
+        let mut section: Section<u64, 4, 4, 4> = Section::new(24);
+        section.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+        assert_eq!(section.bits_per_item(), 24);
+        assert!(section.heap_size() < 2048);
+    }
+
+    #[test]
+    fn test_64_bits_per_item() {
+        let mut section: Section<u64, 4, 4, 4> = SectionBuilder::new()
+            .bits_per_item(64)
+            .min_bits_per_item(64)
+            .try_build()
+            .unwrap();
+        section.set_item(IVec3::new(1, 2, 3), u64::MAX).unwrap();
+        section.set_item(IVec3::new(3, 2, 1), 7).unwrap();
+        assert_eq!(section.bits_per_item(), 64);
+        assert_eq!(*section.item(IVec3::new(1, 2, 3)).unwrap(), u64::MAX);
+
+        let mut section: Section<u64, 4, 4, 4> = SectionBuilder::new()
+            .bits_per_item(1)
+            .growth_policy(GrowthPolicy::Custom(|_, _| 64))
+            .build();
+        for z in 0..4 {
+            section.set_item(IVec3::new(0, 0, z), z as u64 + 1).unwrap();
+        }
+        assert_eq!(section.bits_per_item(), 64);
+        section.fill_region(IVec3::ZERO, IVec3::new(3, 0, 3), 9).unwrap();
+        assert_eq!(section.count_of(&9), 16);
+
+        let mut bytes: Vec<u8> = Vec::new();
+        section.write_to_version(&mut bytes, 1).unwrap();
+        let read: Section<u64, 4, 4, 4> = Section::read_from(&mut bytes.as_slice()).unwrap();
+        assert!(read == section);
+
+        let mut section: Section<u64, 4, 4, 4> = Section::new(200);
+        section.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+        assert_eq!(section.bits_per_item(), 64);
+        assert_eq!(*section.item(IVec3::new(1, 2, 3)).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_compacted_palette_is_deterministic() {
+        let build = || {
+            let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+            section.fill_with(|pos| ((pos.x * 7 + pos.y * 3 + pos.z) % 11) as u64);
+            let mut bytes: Vec<u8> = Vec::new();
+            section.write_to_version(&mut bytes, 1).unwrap();
+            bytes
+        };
+        let bytes: Vec<u8> = build();
+        for _ in 0..8 {
+            assert_eq!(build(), bytes);
+        }
+
+        let mut section: Section<u64, 8, 8, 8> = Section::new(1);
+        section.fill_with(|pos| (9 - pos.z) as u64);
+        let usage: Vec<(&u64, usize)> = section.palette_usage().collect();
+        assert_eq!(usage[..3], [(&9, 64), (&8, 64), (&7, 64)]);
+    }
 }
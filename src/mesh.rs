@@ -0,0 +1,492 @@
+//! CPU meshing of a [`Section`] into plain vertex and index buffers.
+//!
+//! Items are unit cubes at their position in the section. A face is visible when its item
+//! is opaque and the item across it is not, so only opaque items produce faces.
+//! Positions are relative to the first item of the section, and the items just outside it
+//! are read from the [`Neighbors`] given, or treated as not opaque when there are none.
+
+use crate::{ PaletteItem, Section, Storage };
+use glam::IVec3;
+
+/// Side of an item a face points out of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    /// Every face, in the order faces of one item are meshed.
+    pub const ALL: [Self; 6] = [
+        Self::PosX,
+        Self::NegX,
+        Self::PosY,
+        Self::NegY,
+        Self::PosZ,
+        Self::NegZ,
+    ];
+
+    /// Returns the unit vector the face points along.
+    #[inline]
+    pub const fn normal(self) -> IVec3 {
+        match self {
+            Self::PosX => IVec3::X,
+            Self::NegX => IVec3::NEG_X,
+            Self::PosY => IVec3::Y,
+            Self::NegY => IVec3::NEG_Y,
+            Self::PosZ => IVec3::Z,
+            Self::NegZ => IVec3::NEG_Z,
+        }
+    }
+
+    // axis the face points along, then the two axes its quads span,
+    // ordered so the first crossed with the second points along the positive axis
+    #[inline]
+    pub(crate) const fn axes(self) -> [usize; 3] {
+        match self {
+            Self::PosX | Self::NegX => [0, 1, 2],
+            Self::PosY | Self::NegY => [1, 2, 0],
+            Self::PosZ | Self::NegZ => [2, 0, 1],
+        }
+    }
+
+    #[inline]
+    pub(crate) const fn is_positive(self) -> bool {
+        matches!(self, Self::PosX | Self::PosY | Self::PosZ)
+    }
+}
+
+/// Sections next to the one being meshed, used to cull faces on its borders.
+///
+/// # Examples
+///
+/// ```
+/// use glam::IVec3;
+/// use chroma::{ Face, Neighbors, Section };
+///
+/// let mut section: Section<u8, 4, 4, 4> = Section::new(1);
+/// section.set_item(IVec3::new(3, 0, 0), 1).unwrap();
+///
+/// let mut east: Section<u8, 4, 4, 4> = Section::new(1);
+/// east.set_item(IVec3::new(0, 0, 0), 1).unwrap();
+///
+/// let neighbors: Neighbors<u8, 4, 4, 4> = Neighbors::new().with(Face::PosX, &east);
+/// assert_eq!(section.mesh_culled(&Neighbors::new(), |&item| item != 0).quad_count(), 6);
+/// assert_eq!(section.mesh_culled(&neighbors, |&item| item != 0).quad_count(), 5);
+/// ```
+pub struct Neighbors<'a, T: PaletteItem, const W: usize, const H: usize, const D: usize> {
+    sections: [Option<&'a Section<T, W, H, D>>; 6],
+}
+
+impl<'a, T: PaletteItem, const W: usize, const H: usize, const D: usize> Neighbors<'a, T, W, H, D> {
+    /// Creates neighbors with no sections, so every border face is visible.
+    pub const fn new() -> Self {
+        Self { sections: [None; 6] }
+    }
+
+    /// Sets the section across a face of the meshed section.
+    pub const fn with(mut self, face: Face, section: &'a Section<T, W, H, D>) -> Self {
+        self.sections[face as usize] = Some(section);
+        self
+    }
+
+    /// Returns the section across a face of the meshed section, if there is one.
+    #[inline]
+    pub const fn get(&self, face: Face) -> Option<&'a Section<T, W, H, D>> {
+        self.sections[face as usize]
+    }
+}
+
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Default
+for Neighbors<'_, T, W, H, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Quads as plain vertex and index buffers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh<T> {
+    /// Corner positions, four per quad.
+    pub positions: Vec<[f32; 3]>,
+    /// Unit normals, four per quad.
+    pub normals: Vec<[f32; 3]>,
+    /// Item each quad was made from, one per quad.
+    pub quad_items: Vec<T>,
+    /// Two counter clockwise triangles per quad, indexing the positions and normals.
+    pub indices: Vec<u32>,
+}
+
+impl<T> Mesh<T> {
+    /// Creates a mesh with no quads.
+    pub const fn new() -> Self {
+        Self {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            quad_items: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Returns the number of quads.
+    #[inline]
+    pub fn quad_count(&self) -> usize {
+        self.quad_items.len()
+    }
+
+    /// Returns if there are no quads.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.quad_items.is_empty()
+    }
+
+    // adds a quad on the face of the box starting at `min` spanning `size` items
+    // along the face's two axes
+    pub(crate) fn push_quad(&mut self, face: Face, min: IVec3, size: [i32; 2], item: T) {
+        let [axis, u_axis, v_axis] = face.axes();
+        let mut origin: IVec3 = min;
+        if face.is_positive() {
+            origin[axis] += 1;
+        }
+        let mut u: IVec3 = IVec3::ZERO;
+        u[u_axis] = size[0];
+        let mut v: IVec3 = IVec3::ZERO;
+        v[v_axis] = size[1];
+
+        let corners: [IVec3; 4] = if face.is_positive() {
+            [origin, origin + u, origin + u + v, origin + v]
+        } else {
+            [origin, origin + v, origin + u + v, origin + u]
+        };
+
+        let start: u32 = self.positions.len() as u32;
+        self.positions.extend(corners.map(|corner| corner.as_vec3().to_array()));
+        self.normals.extend([face.normal().as_vec3().to_array(); 4]);
+        self.indices.extend([0, 1, 2, 0, 2, 3].map(|offset| start + offset));
+        self.quad_items.push(item);
+    }
+}
+
+impl<T> Default for Mesh<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// which items are opaque, in and just outside of a section
+pub(crate) struct Opacity<'a, T: PaletteItem, const W: usize, const H: usize, const D: usize> {
+    opaque: Vec<bool>,
+    neighbors: &'a Neighbors<'a, T, W, H, D>,
+    is_opaque: &'a dyn Fn(&T) -> bool,
+}
+
+impl<'a, T: PaletteItem, const W: usize, const H: usize, const D: usize> Opacity<'a, T, W, H, D> {
+    // evaluates the predicate once per distinct value where the storage allows it
+    pub(crate) fn new(
+        section: &Section<T, W, H, D>,
+        neighbors: &'a Neighbors<'a, T, W, H, D>,
+        is_opaque: &'a dyn Fn(&T) -> bool
+    ) -> Self {
+        let volume: usize = W * H * D;
+        let opaque: Vec<bool> = match &*section.storage {
+            Storage::Uniform(item) => vec![is_opaque(item); volume],
+            Storage::Indirect { palette, data } => {
+                let entries: Vec<bool> = (0..palette.len())
+                    .map(|palette_index| is_opaque(unsafe { palette.get_unchecked(palette_index) }))
+                    .collect();
+                data.iter(volume)
+                    .map(|palette_index| entries[palette_index as usize])
+                    .collect()
+            }
+            Storage::Direct(data) => data.iter().map(is_opaque).collect(),
+        };
+
+        Self { opaque, neighbors, is_opaque }
+    }
+
+    #[inline]
+    pub(crate) fn is_opaque(&self, pos: IVec3) -> bool {
+        let dimensions: IVec3 = IVec3::new(W as i32, H as i32, D as i32);
+        if pos.cmpge(IVec3::ZERO).all() && pos.cmplt(dimensions).all() {
+            return self.opaque[Section::<T, W, H, D>::item_index(pos)];
+        }
+
+        let face: Face = match pos {
+            _ if pos.x >= dimensions.x => Face::PosX,
+            _ if pos.x < 0 => Face::NegX,
+            _ if pos.y >= dimensions.y => Face::PosY,
+            _ if pos.y < 0 => Face::NegY,
+            _ if pos.z >= dimensions.z => Face::PosZ,
+            _ => Face::NegZ,
+        };
+        self.neighbors.get(face).is_some_and(|neighbor| {
+            let item: &T = neighbor.item(pos.rem_euclid(dimensions)).expect("wrapped in bounds");
+            (self.is_opaque)(item)
+        })
+    }
+
+    // if the face of an item in the section is drawn
+    #[inline]
+    pub(crate) fn is_visible(&self, pos: IVec3, face: Face) -> bool {
+        self.is_opaque(pos) && !self.is_opaque(pos + face.normal())
+    }
+}
+
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Section<T, W, H, D> {
+    /// Builds a quad for every visible face of every opaque item.
+    ///
+    /// Faces between two opaque items are culled, including across the borders
+    /// to any `neighbors` given. Quads are ordered like [`Section::iter`],
+    /// then like [`Face::ALL`] within an item.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::{ Mesh, Neighbors, Section };
+    ///
+    /// let mut section: Section<u8, 16, 16, 16> = Section::new(1);
+    /// section.set_item(IVec3::new(1, 1, 1), 1).unwrap();
+    /// section.set_item(IVec3::new(1, 1, 2), 1).unwrap();
+    ///
+    /// let mesh: Mesh<u8> = section.mesh_culled(&Neighbors::new(), |&item| item != 0);
+    /// assert_eq!(mesh.quad_count(), 10);
+    /// assert_eq!(mesh.positions.len(), 40);
+    /// assert_eq!(mesh.indices.len(), 60);
+    /// ```
+    pub fn mesh_culled(
+        &self,
+        neighbors: &Neighbors<'_, T, W, H, D>,
+        is_opaque: impl Fn(&T) -> bool
+    ) -> Mesh<T> {
+        let opacity: Opacity<'_, T, W, H, D> = Opacity::new(self, neighbors, &is_opaque);
+        let mut mesh: Mesh<T> = Mesh::new();
+
+        for (pos, item) in self.iter() {
+            for face in Face::ALL {
+                if opacity.is_visible(pos, face) {
+                    mesh.push_quad(face, pos, [1, 1], item.clone());
+                }
+            }
+        }
+
+        mesh
+    }
+
+    /// Builds the same visible faces as [`Section::mesh_culled`], merging faces that share
+    /// a plane and an item into as few rectangular quads as it greedily can.
+    ///
+    /// Each slice of the section along each face is swept row by row, and every face not
+    /// yet covered grows as wide and then as tall as faces with the same item allow.
+    /// Quads are ordered like [`Face::ALL`], then by slice, row and column,
+    /// so the same section always gives the same mesh.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::{ Mesh, Neighbors, Section };
+    ///
+    /// let mut section: Section<u8, 16, 16, 16> = Section::new(1);
+    /// section.fill_region(IVec3::new(0, 0, 0), IVec3::new(15, 0, 15), 1).unwrap();
+    ///
+    /// let culled: Mesh<u8> = section.mesh_culled(&Neighbors::new(), |&item| item != 0);
+    /// let greedy: Mesh<u8> = section.mesh_greedy(&Neighbors::new(), |&item| item != 0);
+    /// assert_eq!(culled.quad_count(), 2 * 256 + 4 * 16);
+    /// assert_eq!(greedy.quad_count(), 6);
+    /// ```
+    pub fn mesh_greedy(
+        &self,
+        neighbors: &Neighbors<'_, T, W, H, D>,
+        is_opaque: impl Fn(&T) -> bool
+    ) -> Mesh<T> {
+        let opacity: Opacity<'_, T, W, H, D> = Opacity::new(self, neighbors, &is_opaque);
+        let dimensions: [usize; 3] = [W, H, D];
+        let mut mesh: Mesh<T> = Mesh::new();
+
+        for face in Face::ALL {
+            let [axis, u_axis, v_axis] = face.axes();
+            let (width, height) = (dimensions[u_axis], dimensions[v_axis]);
+            let mut mask: Vec<Option<&T>> = vec![None; width * height];
+
+            for slice in 0..dimensions[axis] {
+                let pos_at = |u: usize, v: usize| {
+                    let mut pos: IVec3 = IVec3::ZERO;
+                    pos[axis] = slice as i32;
+                    pos[u_axis] = u as i32;
+                    pos[v_axis] = v as i32;
+                    pos
+                };
+
+                for v in 0..height {
+                    for u in 0..width {
+                        let pos: IVec3 = pos_at(u, v);
+                        mask[v * width + u] = opacity
+                            .is_visible(pos, face)
+                            .then(|| unsafe { self.item_unchecked(pos) });
+                    }
+                }
+
+                for v in 0..height {
+                    let mut u: usize = 0;
+                    while u < width {
+                        let Some(item) = mask[v * width + u] else {
+                            u += 1;
+                            continue;
+                        };
+
+                        let quad_width: usize = mask[v * width + u..(v + 1) * width]
+                            .iter()
+                            .take_while(|&&other| other == Some(item))
+                            .count();
+                        let quad_height: usize = (v..height)
+                            .take_while(|&row| {
+                                mask[row * width + u..row * width + u + quad_width]
+                                    .iter()
+                                    .all(|&other| other == Some(item))
+                            })
+                            .count();
+
+                        for row in v..v + quad_height {
+                            mask[row * width + u..row * width + u + quad_width].fill(None);
+                        }
+                        mesh.push_quad(
+                            face,
+                            pos_at(u, v),
+                            [quad_width as i32, quad_height as i32],
+                            item.clone()
+                        );
+                        u += quad_width;
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Small = Section<u8, 4, 4, 4>;
+
+    fn is_solid(item: &u8) -> bool {
+        *item == 1
+    }
+
+    #[test]
+    fn test_single_cube() {
+        let mut section: Small = Small::new(1);
+        section.set_item(IVec3::new(2, 0, 3), 1).unwrap();
+        let mesh: Mesh<u8> = section.mesh_culled(&Neighbors::new(), is_solid);
+        assert_eq!(mesh.quad_count(), 6);
+        assert_eq!(mesh.quad_items, [1; 6]);
+
+        // corners of the +x face, counter clockwise seen from +x
+        assert_eq!(mesh.positions[0..4], [
+            [3.0, 0.0, 3.0],
+            [3.0, 1.0, 3.0],
+            [3.0, 1.0, 4.0],
+            [3.0, 0.0, 4.0],
+        ]);
+        assert_eq!(mesh.normals[0], [1.0, 0.0, 0.0]);
+        assert_eq!(mesh.indices[6..12], [4, 5, 6, 4, 6, 7]);
+
+        for quad in 0..6 {
+            let [a, b, c] = [0, 1, 2].map(|corner| {
+                glam::Vec3::from_array(mesh.positions[quad * 4 + corner])
+            });
+            let normal: glam::Vec3 = glam::Vec3::from_array(mesh.normals[quad * 4]);
+            assert_eq!((b - a).cross(c - a), normal);
+        }
+    }
+
+    #[test]
+    fn test_culling() {
+        let mut section: Small = Small::new(2);
+        section.fill(1);
+        assert_eq!(section.mesh_culled(&Neighbors::new(), is_solid).quad_count(), 6 * 16);
+
+        // glass is drawn by another pass and does not hide faces
+        section.set_item(IVec3::new(1, 1, 1), 2).unwrap();
+        assert_eq!(section.mesh_culled(&Neighbors::new(), is_solid).quad_count(), 6 * 16 + 6);
+
+        let full: Small = Small::filled(1, 0);
+        let neighbors: Neighbors<u8, 4, 4, 4> = Face::ALL
+            .into_iter()
+            .fold(Neighbors::new(), |neighbors, face| neighbors.with(face, &full));
+        assert_eq!(section.mesh_culled(&neighbors, is_solid).quad_count(), 6);
+
+        let empty: Small = Small::new(1);
+        let neighbors: Neighbors<u8, 4, 4, 4> = Neighbors::new().with(Face::NegY, &empty);
+        let mesh: Mesh<u8> = full.mesh_culled(&neighbors, is_solid);
+        assert_eq!(mesh.quad_count(), 6 * 16);
+        assert!(full.mesh_culled(&neighbors, |_| false).is_empty());
+    }
+
+    // total area of the quads facing each way
+    fn face_areas(mesh: &Mesh<u8>) -> Vec<([i32; 3], f32)> {
+        let mut areas: Vec<([i32; 3], f32)> = Vec::new();
+        for quad in 0..mesh.quad_count() {
+            let [a, b, c] = [0, 1, 2].map(|corner| {
+                glam::Vec3::from_array(mesh.positions[quad * 4 + corner])
+            });
+            let normal: [i32; 3] = mesh.normals[quad * 4].map(|len| len as i32);
+            let area: f32 = (b - a).cross(c - b).length();
+
+            match areas.iter_mut().find(|(other, _)| *other == normal) {
+                Some((_, total)) => *total += area,
+                None => areas.push((normal, area)),
+            }
+        }
+        areas.sort_by_key(|&(normal, _)| normal);
+        areas
+    }
+
+    #[test]
+    fn test_greedy_matches_culled() {
+        let mut section: Section<u8, 5, 3, 7> = Section::new(2);
+        section.fill_with(|pos| match (pos.x + 2 * pos.y + pos.z) % 4 {
+            0 => 0,
+            1 | 2 => 1,
+            _ => 2,
+        });
+        section.fill_region(IVec3::new(1, 0, 1), IVec3::new(3, 2, 5), 1).unwrap();
+
+        let culled: Mesh<u8> = section.mesh_culled(&Neighbors::new(), |&item| item != 0);
+        let greedy: Mesh<u8> = section.mesh_greedy(&Neighbors::new(), |&item| item != 0);
+        assert!(greedy.quad_count() < culled.quad_count());
+        assert_eq!(face_areas(&greedy), face_areas(&culled));
+        assert_eq!(greedy, section.mesh_greedy(&Neighbors::new(), |&item| item != 0));
+
+        for quad in 0..greedy.quad_count() {
+            let [a, b, c] = [0, 1, 2].map(|corner| {
+                glam::Vec3::from_array(greedy.positions[quad * 4 + corner])
+            });
+            let normal: glam::Vec3 = glam::Vec3::from_array(greedy.normals[quad * 4]);
+            assert_eq!((b - a).cross(c - a).normalize(), normal);
+        }
+    }
+
+    #[test]
+    fn test_greedy_keeps_items_apart() {
+        let mut section: Small = Small::filled(1, 0);
+        assert_eq!(section.mesh_greedy(&Neighbors::new(), is_solid).quad_count(), 6);
+
+        section.fill_region(IVec3::new(0, 0, 2), IVec3::new(3, 3, 3), 3).unwrap();
+        let mesh: Mesh<u8> = section.mesh_greedy(&Neighbors::new(), |&item| item != 0);
+        assert_eq!(mesh.quad_count(), 2 + 4 * 2);
+        assert_eq!(mesh.quad_items.iter().filter(|&&item| item == 3).count(), 5);
+
+        let full: Small = Small::filled(1, 0);
+        let neighbors: Neighbors<u8, 4, 4, 4> = Neighbors::new().with(Face::PosZ, &full);
+        let mesh: Mesh<u8> = section.mesh_greedy(&neighbors, |&item| item != 0);
+        assert_eq!(mesh.quad_count(), 1 + 4 * 2);
+    }
+}
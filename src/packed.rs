@@ -0,0 +1,407 @@
+use crate::Layout;
+use std::collections::TryReserveError;
+
+/// Fixed length array of unsigned integers packed into as few bits as possible.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub(crate) struct PackedArray {
+    data: Vec<u64>,
+    bits_per_item: u8,
+    layout: Layout,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    items_per_word: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    reciprocal: u64,
+}
+
+impl PackedArray {
+    const BITS_PER_WORD: usize = 64;
+
+    /// Creates an array of `len` zeroed items.
+    pub(crate) fn new(len: usize, bits_per_item: u8, layout: Layout) -> Self {
+        let data: Vec<u64> = vec![0; Self::data_len(len, bits_per_item, layout)];
+        Self::with_data(data, bits_per_item, layout)
+    }
+
+    /// Creates an array of `len` items from previously packed words,
+    /// or none if there are not exactly as many words as the length needs.
+    pub(crate) fn from_words(
+        len: usize,
+        bits_per_item: u8,
+        layout: Layout,
+        words: Vec<u64>
+    ) -> Option<Self> {
+        if words.len() != Self::data_len(len, bits_per_item, layout) {
+            return None;
+        }
+        Some(Self::with_data(words, bits_per_item, layout))
+    }
+
+    /// Returns the packed words.
+    #[inline]
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.data
+    }
+
+    /// Takes the packed words, for checking and rebuilding an untrusted array.
+    #[inline]
+    pub(crate) fn into_words(self) -> Vec<u64> {
+        self.data
+    }
+
+    #[cfg(feature = "serde")]
+    #[inline]
+    pub(crate) const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    fn with_data(data: Vec<u64>, bits_per_item: u8, layout: Layout) -> Self {
+        let items_per_word: usize = Self::BITS_PER_WORD / (bits_per_item.max(1) as usize);
+
+        Self {
+            data,
+            bits_per_item,
+            layout,
+            items_per_word: items_per_word as u8,
+            reciprocal: (u64::MAX / (items_per_word as u64)).wrapping_add(1),
+        }
+    }
+
+    /// Returns the number of words needed to hold `len` items.
+    pub(crate) const fn data_len(len: usize, bits_per_item: u8, layout: Layout) -> usize {
+        match layout {
+            Layout::Spanning => ((bits_per_item as usize) * len) / Self::BITS_PER_WORD + 1,
+            Layout::Aligned if bits_per_item == 0 => len.div_ceil(Self::BITS_PER_WORD),
+            Layout::Aligned => len.div_ceil(Self::BITS_PER_WORD / (bits_per_item as usize)),
+        }
+    }
+
+    #[inline]
+    pub(crate) const fn bits_per_item(&self) -> u8 {
+        self.bits_per_item
+    }
+
+    /// Returns the bytes allocated for the packed words.
+    #[inline]
+    pub(crate) fn heap_size(&self) -> usize {
+        self.data.capacity() * size_of::<u64>()
+    }
+
+    /// Gets the item at index.
+    #[inline]
+    pub(crate) fn get(&self, item_index: usize) -> u64 {
+        if self.layout == Layout::Aligned {
+            let (word_index, bit_in_word) = self.aligned_index(item_index);
+            return (self.data[word_index] >> bit_in_word) & Self::mask(self.bits_per_item as usize);
+        }
+
+        let (word_index, bit_in_word) = Self::split_index(item_index, self.bits_per_item);
+
+        let mut item: u64 = self.data[word_index];
+
+        if bit_in_word + (self.bits_per_item as usize) > Self::BITS_PER_WORD {
+            item >>= bit_in_word;
+            let remaining_bits_n: usize =
+                bit_in_word + (self.bits_per_item as usize) - Self::BITS_PER_WORD;
+            let next_word: u64 = self.data[word_index + 1];
+            item |= next_word << ((self.bits_per_item as usize) - remaining_bits_n);
+        } else {
+            item >>= bit_in_word;
+        }
+
+        item & Self::mask(self.bits_per_item as usize)
+    }
+
+    /// Iterates over the first `len` items in order, decoding each word once.
+    #[inline]
+    pub(crate) fn iter(&self, len: usize) -> PackedIter<'_> {
+        PackedIter {
+            array: self,
+            word_index: 0,
+            bit_in_word: 0,
+            remaining: len,
+        }
+    }
+
+    /// Sets the item at index.
+    ///
+    /// # Safety
+    ///
+    /// Index must be less than the array length and value must fit in the bits per item.
+    pub(crate) unsafe fn set_unchecked(&mut self, item_index: usize, value: u64) {
+        debug_assert!(value <= Self::mask(self.bits_per_item as usize), "repack needed first");
+
+        if self.layout == Layout::Aligned {
+            let (word_index, bit_in_word) = self.aligned_index(item_index);
+            let item_mask: u64 = Self::mask(self.bits_per_item as usize);
+
+            unsafe {
+                let word: &mut u64 = self.data.get_unchecked_mut(word_index);
+                *word &= !(item_mask << bit_in_word);
+                *word |= (value & item_mask) << bit_in_word;
+            }
+            return;
+        }
+
+        let (word_index, bit_in_word) = Self::split_index(item_index, self.bits_per_item);
+        let bits_in_first_word: usize = Self::BITS_PER_WORD - bit_in_word;
+
+        unsafe {
+            if (self.bits_per_item as usize) <= bits_in_first_word {
+                let item_mask: u64 = Self::mask(self.bits_per_item as usize);
+                *self.data.get_unchecked_mut(word_index) &= !(item_mask << bit_in_word);
+                *self.data.get_unchecked_mut(word_index) |= (value & item_mask) << bit_in_word;
+            } else {
+                let bits_in_second_word: usize = (self.bits_per_item as usize) - bits_in_first_word;
+                let mask_for_first_word: u64 = Self::mask(bits_in_first_word);
+                *self.data.get_unchecked_mut(word_index) &= !(mask_for_first_word << bit_in_word);
+                *self.data.get_unchecked_mut(word_index) |=
+                    (value & mask_for_first_word) << bit_in_word;
+
+                debug_assert!(
+                    word_index + 1 < self.data.len(),
+                    "should not write beyond data bounds"
+                );
+
+                let mask_for_second_word: u64 = Self::mask(bits_in_second_word);
+                *self.data.get_unchecked_mut(word_index + 1) &= !mask_for_second_word;
+                *self.data.get_unchecked_mut(word_index + 1) |=
+                    (value >> bits_in_first_word) & mask_for_second_word;
+            }
+        }
+    }
+
+    /// Sets `len` consecutive items starting at index to the same value,
+    /// writing whole words at once where the run covers them.
+    ///
+    /// # Safety
+    ///
+    /// The run must lie within the array and value must fit in the bits per item.
+    pub(crate) unsafe fn fill_unchecked(&mut self, start: usize, len: usize, value: u64) {
+        let bits_per_item: usize = self.bits_per_item as usize;
+        let end: usize = start + len;
+
+        // spanning items only line up with word boundaries when the width divides a word
+        if
+            bits_per_item == 0 ||
+            (self.layout == Layout::Spanning && Self::BITS_PER_WORD % bits_per_item != 0)
+        {
+            for item_index in start..end {
+                unsafe {
+                    self.set_unchecked(item_index, value);
+                }
+            }
+            return;
+        }
+
+        let items_per_word: usize = self.items_per_word as usize;
+        let first_word: usize = start.div_ceil(items_per_word);
+        let last_word: usize = (end / items_per_word).max(first_word);
+        let head_end: usize = (first_word * items_per_word).min(end);
+        let tail_start: usize = (last_word * items_per_word).max(head_end);
+
+        for item_index in (start..head_end).chain(tail_start..end) {
+            unsafe {
+                self.set_unchecked(item_index, value);
+            }
+        }
+
+        let mut pattern: u64 = 0;
+        for item_in_word in 0..items_per_word {
+            pattern |= value << (item_in_word * bits_per_item);
+        }
+        self.data[first_word..last_word].fill(pattern);
+    }
+
+    /// Copies the first `len` items into a new array with the given layout,
+    /// returning an error instead of aborting if the words cannot be allocated.
+    pub(crate) fn try_copy(&self, len: usize, layout: Layout) -> Result<Self, TryReserveError> {
+        let data_len: usize = Self::data_len(len, self.bits_per_item, layout);
+        let mut data: Vec<u64> = Vec::new();
+        data.try_reserve_exact(data_len)?;
+
+        if layout == self.layout {
+            data.extend_from_slice(&self.data);
+            return Ok(Self::with_data(data, self.bits_per_item, layout));
+        }
+
+        data.resize(data_len, 0);
+        let mut copy: Self = Self::with_data(data, self.bits_per_item, layout);
+        for (item_index, value) in self.iter(len).enumerate() {
+            unsafe {
+                copy.set_unchecked(item_index, value);
+            }
+        }
+        Ok(copy)
+    }
+
+    /// Copies the first `len` items into a new array with a different amount of bits per item,
+    /// passing each one through remap.
+    pub(crate) fn repacked(
+        &self,
+        len: usize,
+        new_bits_per_item: u8,
+        remap: impl Fn(u64) -> u64
+    ) -> Self {
+        let mut repacked: Self = Self::new(len, new_bits_per_item, self.layout);
+
+        for item_index in 0..len {
+            unsafe {
+                repacked.set_unchecked(item_index, remap(self.get(item_index)));
+            }
+        }
+
+        repacked
+    }
+
+    #[inline]
+    const fn split_index(item_index: usize, bits_per_item: u8) -> (usize, usize) {
+        let bit_offset: usize = item_index * (bits_per_item as usize);
+        let word_index: usize = bit_offset / Self::BITS_PER_WORD;
+        let bit_in_word: usize = bit_offset % Self::BITS_PER_WORD;
+        (word_index, bit_in_word)
+    }
+
+    // items never cross a word, any bits left over at the top of a word are unused
+    #[inline]
+    const fn aligned_index(&self, item_index: usize) -> (usize, usize) {
+        // dividing by multiplying with the precomputed reciprocal of items per word,
+        // which is exact for any index below 2^32
+        debug_assert!(item_index <= (u32::MAX as usize), "index too large for the reciprocal");
+        let word_index: usize = if self.items_per_word == 1 {
+            item_index
+        } else {
+            (((item_index as u128) * (self.reciprocal as u128)) >> 64) as usize
+        };
+        let item_in_word: usize = item_index - word_index * (self.items_per_word as usize);
+        (word_index, item_in_word * (self.bits_per_item as usize))
+    }
+
+    // mask of the lowest bits, valid for every width up to a whole word
+    #[inline]
+    const fn mask(bits: usize) -> u64 {
+        match u64::MAX.checked_shr((Self::BITS_PER_WORD - bits) as u32) {
+            Some(mask) => mask,
+            None => 0,
+        }
+    }
+}
+
+/// Sequential reader over a [`PackedArray`].
+pub(crate) struct PackedIter<'a> {
+    array: &'a PackedArray,
+    word_index: usize,
+    bit_in_word: usize,
+    remaining: usize,
+}
+
+impl Iterator for PackedIter<'_> {
+    type Item = u64;
+
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let bits_per_item: usize = self.array.bits_per_item as usize;
+        if bits_per_item == 0 {
+            return Some(0);
+        }
+
+        if
+            self.array.layout == Layout::Aligned &&
+            self.bit_in_word + bits_per_item > PackedArray::BITS_PER_WORD
+        {
+            self.word_index += 1;
+            self.bit_in_word = 0;
+        }
+
+        let mut item: u64 = self.array.data[self.word_index] >> self.bit_in_word;
+        self.bit_in_word += bits_per_item;
+
+        if self.bit_in_word >= PackedArray::BITS_PER_WORD {
+            self.word_index += 1;
+            self.bit_in_word -= PackedArray::BITS_PER_WORD;
+
+            if self.bit_in_word > 0 {
+                item |= self.array.data[self.word_index] << (bits_per_item - self.bit_in_word);
+            }
+        }
+
+        Some(item & PackedArray::mask(bits_per_item))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for PackedIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layouts_round_trip() {
+        const LEN: usize = 1000;
+
+        for layout in [Layout::Spanning, Layout::Aligned] {
+            for bits_per_item in 0..=64u8 {
+                let mut array: PackedArray = PackedArray::new(LEN, bits_per_item, layout);
+                let mask: u64 = PackedArray::mask(bits_per_item as usize);
+                let value = |item_index: usize| {
+                    (item_index as u64).wrapping_mul(0x9e3779b97f4a7c15) & mask
+                };
+
+                for item_index in 0..LEN {
+                    unsafe {
+                        array.set_unchecked(item_index, value(item_index));
+                    }
+                }
+                for item_index in 0..LEN {
+                    assert_eq!(array.get(item_index), value(item_index), "{layout:?}");
+                }
+                assert!(array.iter(LEN).eq((0..LEN).map(value)), "{layout:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_matches_set() {
+        const LEN: usize = 300;
+
+        for layout in [Layout::Spanning, Layout::Aligned] {
+            for bits_per_item in 0..=64u8 {
+                let value: u64 = PackedArray::mask(bits_per_item as usize) & 0x5a5a5a5a5a5a5a5a;
+
+                for (start, len) in [(0, LEN), (3, 1), (5, 0), (7, 200), (64, 64), (130, 170)] {
+                    let mut filled: PackedArray = PackedArray::new(LEN, bits_per_item, layout);
+                    let mut expected: PackedArray = PackedArray::new(LEN, bits_per_item, layout);
+
+                    unsafe {
+                        filled.fill_unchecked(start, len, value);
+                        for item_index in start..start + len {
+                            expected.set_unchecked(item_index, value);
+                        }
+                    }
+                    assert!(filled.iter(LEN).eq(expected.iter(LEN)), "{layout:?} {bits_per_item}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_aligned_never_spans() {
+        let array: PackedArray = PackedArray::new(4096, 5, Layout::Aligned);
+        assert_eq!(array.data.len(), 4096usize.div_ceil(12));
+
+        for item_index in 0..4096 {
+            let (_, bit_in_word) = array.aligned_index(item_index);
+            assert!(bit_in_word + 5 <= 64);
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{ HashMap, TryReserveError };
+use std::hash::Hash;
+
+/// Values that can be stored in a [`Section`](crate::Section).
+///
+/// Implemented for every type that can be compared, hashed and cloned.
+pub trait PaletteItem: Eq + Hash + Clone {}
+
+impl<T: Eq + Hash + Clone> PaletteItem for T {}
+
+/// Maps items to small indices and tracks how many cells reference each index.
+///
+/// Entries whose count drops to zero are freed and their slot is reused by the next new item.
+/// Once the palette outgrows a linear scan, lookups go through a reverse index instead.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(serialize = "T: serde::Serialize", deserialize = "T: serde::Deserialize<'de>"))
+)]
+#[derive(Clone)]
+pub(crate) struct Palette<T: PaletteItem> {
+    entries: Vec<T>,
+    counts: Vec<usize>,
+    free: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index: Option<HashMap<T, usize>>,
+}
+
+impl<T: PaletteItem> Palette<T> {
+    /// Number of slots above which lookups use the reverse index.
+    pub(crate) const INDEX_THRESHOLD: usize = 16;
+
+    /// Creates a palette holding a single item referenced `count` times.
+    pub(crate) fn new(item: T, count: usize, capacity: usize) -> Self {
+        let mut entries: Vec<T> = Vec::with_capacity(capacity);
+        entries.push(item);
+
+        Self {
+            entries,
+            counts: vec![count],
+            free: Vec::new(),
+            index: None,
+        }
+    }
+
+    /// Creates a palette from items and how many cells reference each,
+    /// assigning indices in the given order.
+    ///
+    /// Items with no references become free slots, the others must be distinct.
+    pub(crate) fn from_usage(usage: Vec<(T, usize)>) -> Self {
+        let (entries, counts): (Vec<T>, Vec<usize>) = usage.into_iter().unzip();
+        let free: Vec<usize> = (0..counts.len())
+            .rev()
+            .filter(|&index| counts[index] == 0)
+            .collect();
+        let mut palette: Self = Self {
+            entries,
+            counts,
+            free,
+            index: None,
+        };
+
+        if palette.entries.len() > Self::INDEX_THRESHOLD {
+            palette.rebuild_index();
+        }
+
+        palette
+    }
+
+    /// Takes every slot's item, including freed ones, in index order.
+    #[cfg(feature = "serde")]
+    pub(crate) fn into_entries(self) -> Vec<T> {
+        self.entries
+    }
+
+    /// Clones the palette, returning an error instead of aborting if it cannot be allocated.
+    pub(crate) fn try_clone(&self) -> Result<Self, TryReserveError> {
+        let mut entries: Vec<T> = Vec::new();
+        entries.try_reserve_exact(self.entries.len())?;
+        entries.extend_from_slice(&self.entries);
+
+        let mut counts: Vec<usize> = Vec::new();
+        counts.try_reserve_exact(self.counts.len())?;
+        counts.extend_from_slice(&self.counts);
+
+        let mut free: Vec<usize> = Vec::new();
+        free.try_reserve_exact(self.free.len())?;
+        free.extend_from_slice(&self.free);
+
+        let index: Option<HashMap<T, usize>> = match &self.index {
+            Some(index) => {
+                let mut copy: HashMap<T, usize> = HashMap::new();
+                copy.try_reserve(index.len())?;
+                copy.extend(
+                    index.iter().map(|(item, &palette_index)| (item.clone(), palette_index))
+                );
+                Some(copy)
+            }
+            None => None,
+        };
+
+        Ok(Self { entries, counts, free, index })
+    }
+
+    /// Returns the number of slots, including freed ones.
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the number of slots referenced by at least one cell.
+    #[inline]
+    pub(crate) fn live_len(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+
+    /// Returns the item stored at a palette index.
+    ///
+    /// # Safety
+    ///
+    /// Index must be less than the palette length.
+    #[inline]
+    pub(crate) unsafe fn get_unchecked(&self, index: usize) -> &T {
+        unsafe { self.entries.get_unchecked(index) }
+    }
+
+    /// Returns the index of a live entry holding the item.
+    #[inline]
+    pub(crate) fn index_of(&self, item: &T) -> Option<usize> {
+        if let Some(index) = &self.index {
+            return index.get(item).copied();
+        }
+
+        self.entries
+            .iter()
+            .zip(&self.counts)
+            .position(|(id, &count)| id == item && count > 0)
+    }
+
+    /// Returns how many cells reference the item.
+    #[inline]
+    pub(crate) fn count_of(&self, item: &T) -> usize {
+        self.index_of(item).map_or(0, |index| self.counts[index])
+    }
+
+    /// Iterates over every live item and how many cells reference it.
+    pub(crate) fn usage(&self) -> impl Iterator<Item = (&T, usize)> + '_ {
+        self.entries
+            .iter()
+            .zip(&self.counts)
+            .filter(|&(_, &count)| count > 0)
+            .map(|(item, &count)| (item, count))
+    }
+
+    /// Returns every live item in index order,
+    /// and where each index would move to if the freed slots were dropped.
+    pub(crate) fn live_remap(&self) -> (Vec<&T>, Vec<usize>) {
+        let mut live: Vec<&T> = Vec::with_capacity(self.live_len());
+        let mut remap: Vec<usize> = vec![0; self.entries.len()];
+
+        for (index, new_index) in remap.iter_mut().enumerate() {
+            if self.counts[index] > 0 {
+                *new_index = live.len();
+                live.push(&self.entries[index]);
+            }
+        }
+
+        (live, remap)
+    }
+
+    /// Returns the slot the next new item will be stored in.
+    #[inline]
+    pub(crate) fn next_index(&self) -> usize {
+        self.free.last().copied().unwrap_or(self.entries.len())
+    }
+
+    /// Stores a new item with no references, reusing a freed slot if there is one.
+    pub(crate) fn insert(&mut self, item: T) -> usize {
+        let new_index: usize = match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = item.clone();
+                index
+            }
+            None => {
+                self.entries.push(item.clone());
+                self.counts.push(0);
+                self.entries.len() - 1
+            }
+        };
+
+        if self.index.is_none() && self.entries.len() > Self::INDEX_THRESHOLD {
+            self.rebuild_index();
+        }
+        if let Some(index) = &mut self.index {
+            index.insert(item, new_index);
+        }
+
+        new_index
+    }
+
+    /// Changes the item a live entry holds, keeping its references.
+    ///
+    /// The new item must not already be live in another entry.
+    pub(crate) fn set_entry(&mut self, index: usize, item: T) {
+        debug_assert!(self.index_of(&item).is_none(), "item is already in the palette");
+
+        if let Some(reverse) = &mut self.index {
+            reverse.remove(&self.entries[index]);
+            reverse.insert(item.clone(), index);
+        }
+        self.entries[index] = item;
+    }
+
+    /// Adds a reference to the entry at index.
+    #[inline]
+    pub(crate) fn acquire(&mut self, index: usize) {
+        self.acquire_many(index, 1);
+    }
+
+    /// Adds `count` references to the entry at index.
+    #[inline]
+    pub(crate) fn acquire_many(&mut self, index: usize, count: usize) {
+        self.counts[index] += count;
+    }
+
+    /// Removes a reference to the entry at index, freeing it once unreferenced.
+    #[inline]
+    pub(crate) fn release(&mut self, index: usize) {
+        self.release_many(index, 1);
+    }
+
+    /// Removes `count` references to the entry at index, freeing it once unreferenced.
+    #[inline]
+    pub(crate) fn release_many(&mut self, index: usize, count: usize) {
+        self.counts[index] -= count;
+
+        if self.counts[index] == 0 {
+            self.free.push(index);
+
+            if let Some(reverse) = &mut self.index {
+                reverse.remove(&self.entries[index]);
+            }
+        }
+    }
+
+    /// Returns the bytes allocated for the entries, counts and reverse index.
+    pub(crate) fn heap_size(&self) -> usize {
+        let index_size: usize = self.index.as_ref().map_or(0, |index| {
+            index.capacity() * (size_of::<T>() + size_of::<usize>())
+        });
+
+        self.entries.capacity() * size_of::<T>() +
+            self.counts.capacity() * size_of::<usize>() +
+            self.free.capacity() * size_of::<usize>() +
+            index_size
+    }
+
+    /// Releases spare capacity.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        self.counts.shrink_to_fit();
+        self.free.shrink_to_fit();
+
+        if let Some(index) = &mut self.index {
+            index.shrink_to_fit();
+        }
+    }
+
+    /// Drops every freed slot and returns where each old index moved to.
+    pub(crate) fn remove_free(&mut self) -> Vec<usize> {
+        let mut remap: Vec<usize> = vec![0; self.entries.len()];
+        let mut new_len: usize = 0;
+
+        for (index, new_index) in remap.iter_mut().enumerate() {
+            if self.counts[index] > 0 {
+                *new_index = new_len;
+                self.entries.swap(new_len, index);
+                self.counts[new_len] = self.counts[index];
+                new_len += 1;
+            }
+        }
+
+        self.entries.truncate(new_len);
+        self.counts.truncate(new_len);
+        self.free.clear();
+
+        if self.entries.len() > Self::INDEX_THRESHOLD {
+            self.rebuild_index();
+        } else {
+            self.index = None;
+        }
+
+        remap
+    }
+
+    // maps every live item back to its slot
+    fn rebuild_index(&mut self) {
+        let index: HashMap<T, usize> = self.entries
+            .iter()
+            .zip(&self.counts)
+            .enumerate()
+            .filter(|&(_, (_, &count))| count > 0)
+            .map(|(palette_index, (item, _))| (item.clone(), palette_index))
+            .collect();
+        self.index = Some(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_follows_threshold() {
+        let mut palette: Palette<u64> = Palette::new(0, 1, 1);
+
+        for item in 1..(Palette::<u64>::INDEX_THRESHOLD as u64) {
+            let index: usize = palette.insert(item);
+            palette.acquire(index);
+        }
+        assert!(palette.index.is_none());
+
+        let index: usize = palette.insert(100);
+        palette.acquire(index);
+        assert!(palette.index.is_some());
+        assert_eq!(palette.index_of(&0), Some(0));
+        assert_eq!(palette.index_of(&3), Some(3));
+        assert_eq!(palette.index_of(&100), Some(Palette::<u64>::INDEX_THRESHOLD));
+
+        palette.release(0);
+        assert_eq!(palette.index_of(&0), None);
+        assert_eq!(palette.insert(99), 0);
+        assert_eq!(palette.index_of(&99), Some(0));
+    }
+}
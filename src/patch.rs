@@ -0,0 +1,355 @@
+use crate::{ BinaryItem, Error, FormatError, PaletteItem, Section, ValidationError };
+use crate::binary::{ read_varint, write_varint };
+use glam::IVec3;
+use std::collections::HashMap;
+use std::io::{ self, Read, Write };
+use std::sync::Arc;
+
+/// Items that differ between two sections, made by [`Section::diff`] and used by
+/// [`Section::apply`].
+///
+/// Changed items are stored by value in a small palette of their own,
+/// so sections compare and patch by value whatever their palettes hold.
+///
+/// The binary form written by [`SectionPatch::write_to`] is, as LEB128 varints,
+/// the dimensions, the palette length followed by each item,
+/// then the change count followed by each change as the gap since the previous changed item
+/// and its palette index. Gaps follow the same order as [`Section::iter`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionPatch<T> {
+    dimensions: [u32; 3],
+    palette: Vec<T>,
+    changes: Vec<(u32, u32)>,
+}
+
+impl<T: PaletteItem> SectionPatch<T> {
+    /// Returns the dimensions of the sections the patch was made from.
+    #[inline]
+    pub const fn dimensions(&self) -> IVec3 {
+        let [width, height, depth] = self.dimensions;
+        IVec3::new(width as i32, height as i32, depth as i32)
+    }
+
+    /// Returns the number of changed items.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns if no items changed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the number of distinct values the changed items are set to.
+    #[inline]
+    pub fn palette_len(&self) -> usize {
+        self.palette.len()
+    }
+
+    /// Iterates over every changed position and its new value, in the same order as
+    /// [`Section::iter`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the patch is invalid, which only a deserialized patch can be.
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, &T)> + '_ {
+        let [_, height, depth] = self.dimensions;
+
+        self.changes.iter().map(move |&(item_index, palette_index)| {
+            let pos: IVec3 = IVec3::new(
+                (item_index / (height * depth)) as i32,
+                ((item_index / depth) % height) as i32,
+                (item_index % depth) as i32
+            );
+            (pos, &self.palette[palette_index as usize])
+        })
+    }
+
+    // checks every change refers to an item of the section and an entry of the palette
+    fn check(&self, volume: usize) -> Result<(), ValidationError> {
+        for &(item_index, palette_index) in &self.changes {
+            if item_index as usize >= volume {
+                return Err(ValidationError::ItemIndexOutOfRange {
+                    item_index: item_index as usize,
+                    volume,
+                });
+            }
+            if palette_index as usize >= self.palette.len() {
+                return Err(ValidationError::PaletteIndexOutOfRange {
+                    item_index: item_index as usize,
+                    palette_index: palette_index as u64,
+                    palette_len: self.palette.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: PaletteItem + BinaryItem> SectionPatch<T> {
+    /// Writes the patch in its compact binary form.
+    ///
+    /// Returns an error if the changes are out of order, which only a deserialized patch can be.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::{ Section, SectionPatch };
+    ///
+    /// let old: Section<u64, 16, 16, 16> = Section::new(2);
+    /// let mut new: Section<u64, 16, 16, 16> = old.clone();
+    /// new.fill_region(IVec3::new(0, 0, 0), IVec3::new(0, 0, 15), 3).unwrap();
+    ///
+    /// let mut bytes: Vec<u8> = Vec::new();
+    /// old.diff(&new).write_to(&mut bytes).unwrap();
+    /// assert!(bytes.len() < 50);
+    ///
+    /// let patch: SectionPatch<u64> = SectionPatch::read_from(&mut bytes.as_slice()).unwrap();
+    /// assert_eq!(patch.len(), 16);
+    /// ```
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        for len in self.dimensions {
+            write_varint(writer, len as u64)?;
+        }
+
+        write_varint(writer, self.palette.len() as u64)?;
+        for item in &self.palette {
+            item.write_item(writer)?;
+        }
+
+        write_varint(writer, self.changes.len() as u64)?;
+        let mut next_index: u32 = 0;
+        for &(item_index, palette_index) in &self.changes {
+            let Some(gap) = item_index.checked_sub(next_index) else {
+                let message: &str = "changes are out of order";
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            };
+            write_varint(writer, gap as u64)?;
+            write_varint(writer, palette_index as u64)?;
+            next_index = item_index + 1;
+        }
+        Ok(())
+    }
+
+    /// Reads a patch written by [`SectionPatch::write_to`].
+    /// Returns an error if the data cannot be read or a change is outside the section or palette.
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let mut dimensions: [u32; 3] = [0; 3];
+        for len in &mut dimensions {
+            *len = u32::try_from(read_varint(reader)?).map_err(|_| {
+                FormatError::InvalidHeader("dimension is too large")
+            })?;
+        }
+        let volume: u64 = dimensions.iter().map(|&len| len as u64).product();
+        if volume > u32::MAX as u64 {
+            return Err(FormatError::InvalidHeader("patch is too large"));
+        }
+
+        let palette_len: u64 = read_varint(reader)?;
+        let mut palette: Vec<T> = Vec::new();
+        for _ in 0..palette_len {
+            palette.push(T::read_item(reader)?);
+        }
+
+        let change_count: u64 = read_varint(reader)?;
+        let mut changes: Vec<(u32, u32)> = Vec::new();
+        let mut next_index: u64 = 0;
+        for _ in 0..change_count {
+            let item_index: u64 = next_index.saturating_add(read_varint(reader)?);
+            let palette_index: u64 = read_varint(reader)?;
+            if item_index >= volume {
+                return Err(
+                    ValidationError::ItemIndexOutOfRange {
+                        item_index: item_index as usize,
+                        volume: volume as usize,
+                    }.into()
+                );
+            }
+
+            changes.push((item_index as u32, palette_index.min(u32::MAX as u64) as u32));
+            next_index = item_index + 1;
+        }
+
+        let patch: Self = Self {
+            dimensions,
+            palette,
+            changes,
+        };
+        patch.check(volume as usize)?;
+        Ok(patch)
+    }
+}
+
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Section<T, W, H, D> {
+    /// Returns the items that differ in `other`, which [`Section::apply`] turns this section into.
+    ///
+    /// Items are compared by value, so the palettes and bits per item of the sections
+    /// do not matter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glam::IVec3;
+    /// use chroma::{ Section, SectionPatch };
+    ///
+    /// let mut section: Section<u64, 16, 16, 16> = Section::new(2);
+    /// let mut other: Section<u64, 16, 16, 16> = Section::new(8);
+    /// other.set_item(IVec3::new(1, 2, 3), 5).unwrap();
+    ///
+    /// let patch: SectionPatch<u64> = section.diff(&other);
+    /// assert_eq!(patch.iter().collect::<Vec<_>>(), [(IVec3::new(1, 2, 3), &5)]);
+    ///
+    /// section.apply(&patch).unwrap();
+    /// assert!(section.diff(&other).is_empty());
+    /// ```
+    pub fn diff(&self, other: &Self) -> SectionPatch<T> {
+        let mut patch: SectionPatch<T> = SectionPatch {
+            dimensions: [W as u32, H as u32, D as u32],
+            palette: Vec::new(),
+            changes: Vec::new(),
+        };
+        if Arc::ptr_eq(&self.storage, &other.storage) {
+            return patch;
+        }
+
+        let mut indices: HashMap<&T, u32> = HashMap::new();
+        for (item_index, (item, new)) in self.values().zip(other.values()).enumerate() {
+            if item == new {
+                continue;
+            }
+
+            let palette_index: u32 = *indices.entry(new).or_insert_with(|| {
+                patch.palette.push(new.clone());
+                (patch.palette.len() - 1) as u32
+            });
+            patch.changes.push((item_index as u32, palette_index));
+        }
+
+        patch
+    }
+
+    /// Sets every item changed by a patch made with [`Section::diff`].
+    /// Returns an error if the patch is for other dimensions or is outside the section or palette.
+    ///
+    /// The whole patch is checked first, so an invalid patch leaves the section unchanged.
+    pub fn apply(&mut self, patch: &SectionPatch<T>) -> Result<(), Error> {
+        if patch.dimensions() != self.dimensions() {
+            return Err(Error::DimensionMismatch {
+                expected: self.dimensions(),
+                found: patch.dimensions(),
+            });
+        }
+        patch.check(Self::VOLUME)?;
+
+        for &(item_index, palette_index) in &patch.changes {
+            let item: T = patch.palette[palette_index as usize].clone();
+            unsafe {
+                self.set_item_at(item_index as usize, item);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Small = Section<u64, 4, 4, 4>;
+
+    #[test]
+    fn test_diff_by_value() {
+        let mut section: Small = Small::new(4);
+        for value in 1..5 {
+            section.set_item(IVec3::new(0, 0, value as i32 - 1), value).unwrap();
+        }
+        section.fill_region(IVec3::ZERO, IVec3::new(0, 0, 3), 0).unwrap();
+        section.set_item(IVec3::new(3, 3, 3), 2).unwrap();
+
+        let mut other: Small = Small::new(1);
+        other.set_item(IVec3::new(3, 3, 3), 2).unwrap();
+        assert!(section.diff(&other).is_empty());
+        assert!(section.diff(&section.snapshot()).is_empty());
+
+        other.fill_region(IVec3::new(1, 0, 0), IVec3::new(1, 0, 3), 7).unwrap();
+        other.set_item(IVec3::new(3, 3, 3), 0).unwrap();
+        let patch: SectionPatch<u64> = section.diff(&other);
+        assert_eq!(patch.len(), 5);
+        assert_eq!(patch.palette_len(), 2);
+
+        section.apply(&patch).unwrap();
+        assert!(section.values().eq(other.values()));
+    }
+
+    #[test]
+    fn test_patch_round_trip() {
+        let section: Section<u64, 8, 8, 8> = Section::new(1);
+        let mut other: Section<u64, 8, 8, 8> = Section::new(1);
+        other.fill_with(|pos| if pos.x == 7 { 300 } else { 0 });
+        other.set_item(IVec3::new(0, 0, 0), 1).unwrap();
+
+        let patch: SectionPatch<u64> = section.diff(&other);
+        let mut bytes: Vec<u8> = Vec::new();
+        patch.write_to(&mut bytes).unwrap();
+        // dimensions, palette, count, then one byte per gap and palette index
+        assert_eq!(bytes.len(), 3 + 1 + 16 + 1 + 65 * 2 + 1);
+
+        let read: SectionPatch<u64> = SectionPatch::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, patch);
+    }
+
+    #[test]
+    fn test_invalid_patches_are_rejected() {
+        let mut other: Small = Small::new(1);
+        other.set_item(IVec3::new(3, 3, 3), 1).unwrap();
+        let patch: SectionPatch<u64> = Small::new(1).diff(&other);
+
+        let mut bytes: Vec<u8> = Vec::new();
+        patch.write_to(&mut bytes).unwrap();
+        // palette index of the only change
+        *bytes.last_mut().unwrap() = 1;
+        let result = SectionPatch::<u64>::read_from(&mut bytes.as_slice());
+        assert!(
+            matches!(
+                result,
+                Err(FormatError::Invalid(ValidationError::PaletteIndexOutOfRange { .. }))
+            )
+        );
+
+        let mut larger: Section<u64, 8, 8, 8> = Section::new(1);
+        let wrong_size: SectionPatch<u64> = SectionPatch {
+            dimensions: [4; 3],
+            ..patch.clone()
+        };
+        assert!(matches!(larger.apply(&wrong_size), Err(Error::DimensionMismatch { .. })));
+
+        let mut section: Small = Small::new(1);
+        let outside: SectionPatch<u64> = SectionPatch {
+            changes: vec![(0, 0), (64, 0)],
+            ..patch
+        };
+        assert!(matches!(section.apply(&outside), Err(Error::Invalid(_))));
+        assert!(section.is_empty());
+    }
+
+    #[test]
+    fn test_huge_counts_are_not_preallocated() {
+        let mut bytes: Vec<u8> = Vec::new();
+        for value in [65535, 65535, 1, u64::MAX] {
+            write_varint(&mut bytes, value).unwrap();
+        }
+        let result = SectionPatch::<u64>::read_from(&mut bytes.as_slice());
+        assert!(matches!(result, Err(FormatError::Io(_))));
+
+        let mut bytes: Vec<u8> = Vec::new();
+        for value in [65535, 65535, 1, 0, u64::MAX] {
+            write_varint(&mut bytes, value).unwrap();
+        }
+        let result = SectionPatch::<u64>::read_from(&mut bytes.as_slice());
+        assert!(matches!(result, Err(FormatError::Io(_))));
+    }
+}
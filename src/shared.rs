@@ -0,0 +1,360 @@
+use crate::{ BoundsError, PaletteItem, Section, ValidationError };
+use glam::IVec3;
+use std::collections::HashMap;
+use std::sync::{ Arc, PoisonError, RwLock };
+
+/// Identifier of a value interned in a [`SharedPalette`].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct GlobalId(u32);
+
+impl GlobalId {
+    /// Returns the raw id.
+    #[inline]
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    #[inline]
+    pub(crate) const fn from_raw(id: u32) -> Self {
+        Self(id)
+    }
+}
+
+struct Interner<T> {
+    values: Vec<Arc<T>>,
+    ids: HashMap<Arc<T>, GlobalId>,
+}
+
+/// Palette of values shared between many sections.
+///
+/// Each distinct value is stored once and given a [`GlobalId`] that never changes,
+/// so sections only need to hold ids. Cloning the handle shares the same palette.
+///
+/// # Examples
+///
+/// ```
+/// use chroma::SharedPalette;
+///
+/// let palette: SharedPalette<String> = SharedPalette::new();
+/// let stone = palette.intern("stone".to_string());
+///
+/// assert_eq!(palette.clone().intern("stone".to_string()), stone);
+/// assert_eq!(*palette.get(stone).unwrap(), "stone");
+/// ```
+pub struct SharedPalette<T: PaletteItem> {
+    inner: Arc<RwLock<Interner<T>>>,
+}
+
+impl<T: PaletteItem> SharedPalette<T> {
+    /// Creates an empty shared palette.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(
+                RwLock::new(Interner {
+                    values: Vec::new(),
+                    ids: HashMap::new(),
+                })
+            ),
+        }
+    }
+
+    /// Returns the id of a value, adding it to the palette if it is new.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `u32::MAX` distinct values are interned.
+    pub fn intern(&self, item: T) -> GlobalId {
+        if let Some(id) = self.id_of(&item) {
+            return id;
+        }
+
+        let mut interner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+
+        // another handle may have interned it between the two locks
+        if let Some(&id) = interner.ids.get(&item) {
+            return id;
+        }
+
+        let id: GlobalId = GlobalId(
+            u32::try_from(interner.values.len()).expect("shared palette is full")
+        );
+        let item: Arc<T> = Arc::new(item);
+        interner.values.push(Arc::clone(&item));
+        interner.ids.insert(item, id);
+        id
+    }
+
+    /// Returns the id of a value if it has been interned.
+    pub fn id_of(&self, item: &T) -> Option<GlobalId> {
+        let interner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        interner.ids.get(item).copied()
+    }
+
+    /// Returns the value behind an id, if the id belongs to this palette.
+    pub fn get(&self, id: GlobalId) -> Option<Arc<T>> {
+        let interner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        interner.values.get(id.0 as usize).cloned()
+    }
+
+    /// Returns the number of distinct values interned.
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner).values.len()
+    }
+
+    /// Returns if no values have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns if both handles refer to the same palette.
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Returns every interned value, ordered by id.
+    pub fn values(&self) -> Vec<Arc<T>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner).values.clone()
+    }
+}
+
+impl<T: PaletteItem> Default for SharedPalette<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PaletteItem> Clone for SharedPalette<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: PaletteItem> FromIterator<T> for SharedPalette<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let palette: Self = Self::new();
+        for item in iter {
+            palette.intern(item);
+        }
+        palette
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: PaletteItem + serde::Serialize> serde::Serialize for SharedPalette<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let interner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        serializer.collect_seq(interner.values.iter().map(|item| item.as_ref()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: PaletteItem + serde::Deserialize<'de>> serde::Deserialize<'de> for SharedPalette<T> {
+    fn deserialize<De: serde::Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let values: Vec<T> = Vec::deserialize(deserializer)?;
+        let len: usize = values.len();
+        let palette: Self = values.into_iter().collect();
+
+        if palette.len() != len {
+            return Err(serde::de::Error::custom("shared palette contains duplicate values"));
+        }
+
+        Ok(palette)
+    }
+}
+
+/// Section whose items are ids into a [`SharedPalette`].
+///
+/// The section's own palette maps packed indices to global ids,
+/// so every value is stored once no matter how many sections use it.
+///
+/// # Examples
+///
+/// ```
+/// use glam::IVec3;
+/// use chroma::{ SharedPalette, SharedSection };
+///
+/// let palette: SharedPalette<String> = SharedPalette::new();
+/// let mut a: SharedSection<String, 16, 16, 16> = SharedSection::new(&palette, 2);
+/// let mut b: SharedSection<String, 16, 16, 16> = SharedSection::new(&palette, 2);
+///
+/// a.set_item(IVec3::new(0, 0, 0), "stone".to_string()).unwrap();
+/// b.set_item(IVec3::new(1, 1, 1), "stone".to_string()).unwrap();
+///
+/// assert_eq!(*a.item(IVec3::new(0, 0, 0)).unwrap(), "stone");
+/// assert_eq!(palette.len(), 2);
+/// ```
+#[derive(Clone)]
+pub struct SharedSection<T: PaletteItem, const W: usize, const H: usize, const D: usize> {
+    section: Section<GlobalId, W, H, D>,
+    palette: SharedPalette<T>,
+}
+
+impl<T: PaletteItem + Default, const W: usize, const H: usize, const D: usize> SharedSection<
+    T,
+    W,
+    H,
+    D
+> {
+    /// Creates a new section using `palette`, with every item set to its default value.
+    pub fn new(palette: &SharedPalette<T>, bits_per_item: u8) -> Self {
+        Self::filled(palette, T::default(), bits_per_item)
+    }
+}
+
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> SharedSection<T, W, H, D> {
+    /// Creates a new section using `palette`, with every item set to `item`.
+    pub fn filled(palette: &SharedPalette<T>, item: T, bits_per_item: u8) -> Self {
+        let id: GlobalId = palette.intern(item);
+
+        Self {
+            section: Section::filled(id, bits_per_item),
+            palette: palette.clone(),
+        }
+    }
+
+    /// Joins a section of ids with the palette they were interned in.
+    /// Returns an error if the section holds an id missing from the palette.
+    pub fn from_parts(
+        section: Section<GlobalId, W, H, D>,
+        palette: SharedPalette<T>
+    ) -> Result<Self, ValidationError> {
+        let palette_len: usize = palette.len();
+        let missing: Option<GlobalId> = section
+            .palette_usage()
+            .map(|(&id, _)| id)
+            .find(|id| id.get() as usize >= palette_len);
+
+        if let Some(id) = missing {
+            let item_index: usize = section
+                .values()
+                .position(|&item| item == id)
+                .unwrap_or_default();
+            return Err(ValidationError::PaletteIndexOutOfRange {
+                item_index,
+                palette_index: id.get() as u64,
+                palette_len,
+            });
+        }
+
+        Ok(Self { section, palette })
+    }
+
+    /// Splits into the section of ids and its palette.
+    pub fn into_parts(self) -> (Section<GlobalId, W, H, D>, SharedPalette<T>) {
+        (self.section, self.palette)
+    }
+
+    /// Returns the underlying section of ids.
+    #[inline]
+    pub const fn section(&self) -> &Section<GlobalId, W, H, D> {
+        &self.section
+    }
+
+    /// Returns the underlying section of ids mutably.
+    #[inline]
+    pub fn section_mut(&mut self) -> &mut Section<GlobalId, W, H, D> {
+        &mut self.section
+    }
+
+    /// Returns the shared palette.
+    #[inline]
+    pub const fn palette(&self) -> &SharedPalette<T> {
+        &self.palette
+    }
+
+    /// Gets an item given its three dimensional position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an id missing from the palette was stored through [`SharedSection::section_mut`].
+    pub fn item(&self, pos: IVec3) -> Result<Arc<T>, BoundsError> {
+        let id: GlobalId = *self.section.item(pos)?;
+        Ok(self.palette.get(id).expect("id is out of range for the shared palette"))
+    }
+
+    /// Sets an item at the given three dimensional position, interning it if it is new.
+    pub fn set_item(&mut self, pos: IVec3, item: T) -> Result<(), BoundsError> {
+        // only values actually stored are added to the palette every section shares
+        Section::<GlobalId, W, H, D>::check_position_in_bounds(pos)?;
+        let id: GlobalId = self.palette.intern(item);
+        self.section.set_item(pos, id)
+    }
+
+    /// Returns how many items in the section are equal to `item`.
+    pub fn count_of(&self, item: &T) -> usize {
+        self.palette.id_of(item).map_or(0, |id| self.section.count_of(&id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_shared() {
+        let palette: SharedPalette<&str> = SharedPalette::new();
+        let mut sections: Vec<SharedSection<&str, 4, 4, 4>> = (0..8)
+            .map(|_| SharedSection::filled(&palette, "air", 1))
+            .collect();
+
+        for (i, section) in sections.iter_mut().enumerate() {
+            let pos: IVec3 = IVec3::new(i as i32 % 4, 0, 0);
+            section.set_item(pos, "stone").unwrap();
+            let item: &str = if i % 2 == 0 { "dirt" } else { "grass" };
+            section.set_item(IVec3::new(3, 3, 3), item).unwrap();
+        }
+
+        assert_eq!(palette.len(), 4);
+        assert_eq!(*sections[5].item(IVec3::new(1, 0, 0)).unwrap(), "stone");
+        assert_eq!(*sections[5].item(IVec3::new(3, 3, 3)).unwrap(), "grass");
+        assert_eq!(*sections[6].item(IVec3::new(3, 3, 3)).unwrap(), "dirt");
+        assert_eq!(sections[2].count_of(&"air"), 62);
+        assert_eq!(sections[2].count_of(&"grass"), 0);
+
+        let stone: GlobalId = palette.id_of(&"stone").unwrap();
+        assert!(sections.iter().all(|section| section.section().count_of(&stone) == 1));
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let palette: SharedPalette<u64> = [0, 10, 20].into_iter().collect();
+        let mut section: Section<GlobalId, 4, 4, 4> = Section::new(1);
+        section.set_item(IVec3::new(2, 2, 2), palette.id_of(&20).unwrap()).unwrap();
+
+        let shared: SharedSection<u64, 4, 4, 4> = SharedSection::from_parts(
+            section,
+            palette
+        ).unwrap();
+        assert_eq!(*shared.item(IVec3::new(2, 2, 2)).unwrap(), 20);
+        assert_eq!(*shared.item(IVec3::new(0, 0, 0)).unwrap(), 0);
+
+        let (mut section, palette) = shared.into_parts();
+        section.set_item(IVec3::new(1, 0, 0), GlobalId::from_raw(3)).unwrap();
+        let error: ValidationError = SharedSection::<u64, 4, 4, 4>::from_parts(
+            section,
+            palette
+        ).err().unwrap();
+        assert_eq!(error, ValidationError::PaletteIndexOutOfRange {
+            item_index: 16,
+            palette_index: 3,
+            palette_len: 3,
+        });
+
+        let section: Section<GlobalId, 4, 4, 4> = Section::new(1);
+        assert!(SharedSection::<u64, 4, 4, 4>::from_parts(section, SharedPalette::new()).is_err());
+    }
+
+    #[test]
+    fn test_out_of_bounds_is_not_interned() {
+        let palette: SharedPalette<u64> = SharedPalette::new();
+        let mut section: SharedSection<u64, 4, 4, 4> = SharedSection::new(&palette, 1);
+
+        assert!(section.set_item(IVec3::new(4, 0, 0), 7).is_err());
+        assert_eq!(palette.len(), 1);
+        assert_eq!(palette.id_of(&7), None);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{ Layout, PaletteItem, Section, Storage };
+use crate::packed::PackedArray;
+use crate::palette::Palette;
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Reason stored section data breaks the invariants a [`Section`] relies on.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("Bits per item {0} is more than 64.")] BitsPerItemTooLarge(u8),
+    #[error("Minimum bits per item {min} exceed the maximum {max}.")] MinAboveMax {
+        min: u8,
+        max: u8,
+    },
+    #[error("Expected {expected} packed words but found {found}.")] DataLength {
+        expected: usize,
+        found: usize,
+    },
+    #[error("Expected {expected} items but found {found}.")] ItemCount {
+        expected: usize,
+        found: usize,
+    },
+    #[error(
+        "Item {item_index} refers to palette index {palette_index} of only {palette_len}."
+    )] PaletteIndexOutOfRange {
+        item_index: usize,
+        palette_index: u64,
+        palette_len: usize,
+    },
+    #[error("Palette index {0} holds a value already in the palette.")] DuplicatePaletteEntry(
+        usize,
+    ),
+    #[error("Item {item_index} is outside a section of {volume} items.")] ItemIndexOutOfRange {
+        item_index: usize,
+        volume: usize,
+    },
+    #[error(
+        "Packed words use the {found:?} layout but the section uses {expected:?}."
+    )] LayoutMismatch {
+        expected: Layout,
+        found: Layout,
+    },
+}
+
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> Section<T, W, H, D> {
+    // checks the bits per item settings any storage can be created with
+    pub(crate) fn check_settings(
+        initial_bits_per_item: u8,
+        min_bits_per_item: u8,
+        max_bits_per_item: u8
+    ) -> Result<(), ValidationError> {
+        for bits_per_item in [initial_bits_per_item, min_bits_per_item] {
+            if bits_per_item > 64 {
+                return Err(ValidationError::BitsPerItemTooLarge(bits_per_item));
+            }
+        }
+        if min_bits_per_item > max_bits_per_item {
+            return Err(ValidationError::MinAboveMax {
+                min: min_bits_per_item,
+                max: max_bits_per_item,
+            });
+        }
+        Ok(())
+    }
+
+    // rebuilds packed storage from untrusted parts,
+    // recounting every palette reference from the indices themselves
+    pub(crate) fn checked_indirect(
+        entries: Vec<T>,
+        bits_per_item: u8,
+        layout: Layout,
+        words: Vec<u64>
+    ) -> Result<Storage<T>, ValidationError> {
+        if bits_per_item > 64 {
+            return Err(ValidationError::BitsPerItemTooLarge(bits_per_item));
+        }
+
+        let found: usize = words.len();
+        let Some(data) = PackedArray::from_words(Self::VOLUME, bits_per_item, layout, words) else {
+            let expected: usize = PackedArray::data_len(Self::VOLUME, bits_per_item, layout);
+            return Err(ValidationError::DataLength { expected, found });
+        };
+
+        let mut counts: Vec<usize> = vec![0; entries.len()];
+        for (item_index, palette_index) in data.iter(Self::VOLUME).enumerate() {
+            let Some(count) = counts.get_mut(palette_index as usize) else {
+                return Err(ValidationError::PaletteIndexOutOfRange {
+                    item_index,
+                    palette_index,
+                    palette_len: entries.len(),
+                });
+            };
+            *count += 1;
+        }
+
+        let mut live: HashSet<&T> = HashSet::with_capacity(entries.len());
+        for (palette_index, item) in entries.iter().enumerate() {
+            if counts[palette_index] > 0 && !live.insert(item) {
+                return Err(ValidationError::DuplicatePaletteEntry(palette_index));
+            }
+        }
+
+        Ok(Storage::Indirect {
+            palette: Palette::from_usage(entries.into_iter().zip(counts).collect()),
+            data,
+        })
+    }
+
+    // checks direct storage holds exactly one item per position
+    #[cfg(feature = "serde")]
+    pub(crate) fn checked_direct(data: Vec<T>) -> Result<Storage<T>, ValidationError> {
+        if data.len() != Self::VOLUME {
+            return Err(ValidationError::ItemCount {
+                expected: Self::VOLUME,
+                found: data.len(),
+            });
+        }
+        Ok(Storage::Direct(data))
+    }
+}
+
+/// Section as it is deserialized, before any of it is trusted.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>"))]
+pub(crate) struct RawSection<T: PaletteItem> {
+    storage: Storage<T>,
+    initial_bits_per_item: u8,
+    #[serde(default)]
+    compaction_policy: crate::CompactionPolicy,
+    #[serde(default)]
+    direct_threshold: Option<u8>,
+    #[serde(default)]
+    min_bits_per_item: u8,
+    #[serde(default = "crate::SectionBuilder::max_bits_per_item_default")]
+    max_bits_per_item: u8,
+    #[serde(default)]
+    layout: Layout,
+}
+
+#[cfg(feature = "serde")]
+impl<T: PaletteItem, const W: usize, const H: usize, const D: usize> TryFrom<RawSection<T>>
+for Section<T, W, H, D> {
+    type Error = ValidationError;
+
+    fn try_from(raw: RawSection<T>) -> Result<Self, ValidationError> {
+        Self::check_settings(
+            raw.initial_bits_per_item,
+            raw.min_bits_per_item,
+            raw.max_bits_per_item
+        )?;
+
+        let storage: Storage<T> = match raw.storage {
+            Storage::Uniform(item) => Storage::Uniform(item),
+            Storage::Indirect { palette, data } => {
+                let bits_per_item: u8 = data.bits_per_item();
+                let layout: Layout = data.layout();
+                if layout != raw.layout {
+                    return Err(ValidationError::LayoutMismatch {
+                        expected: raw.layout,
+                        found: layout,
+                    });
+                }
+                let words: Vec<u64> = data.into_words();
+                Self::checked_indirect(palette.into_entries(), bits_per_item, layout, words)?
+            }
+            Storage::Direct(data) => Self::checked_direct(data)?,
+        };
+
+        Ok(Self {
+            storage: std::sync::Arc::new(storage),
+            initial_bits_per_item: raw.initial_bits_per_item,
+            compaction_policy: raw.compaction_policy,
+            direct_threshold: raw.direct_threshold,
+            growth_policy: crate::GrowthPolicy::default(),
+            min_bits_per_item: raw.min_bits_per_item,
+            max_bits_per_item: raw.max_bits_per_item,
+            layout: raw.layout,
+            changes: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ FormatError, SectionBuilder };
+    use glam::IVec3;
+
+    type Small = Section<u64, 4, 4, 4>;
+
+    #[test]
+    fn test_checked_indirect() {
+        let words: Vec<u64> = vec![0; PackedArray::data_len(64, 2, Layout::Spanning)];
+        let mut storage = Small::checked_indirect(vec![5, 6], 2, Layout::Spanning, words).unwrap();
+        let Storage::Indirect { palette, .. } = &mut storage else {
+            panic!("expected packed storage");
+        };
+        assert_eq!(palette.count_of(&5), 64);
+        assert_eq!(palette.live_len(), 1);
+        assert_eq!(palette.next_index(), 1);
+
+        let words: Vec<u64> = vec![u64::MAX; PackedArray::data_len(64, 2, Layout::Spanning)];
+        let result = Small::checked_indirect(vec![5, 6, 7], 2, Layout::Spanning, words);
+        assert_eq!(
+            result.err(),
+            Some(ValidationError::PaletteIndexOutOfRange {
+                item_index: 0,
+                palette_index: 3,
+                palette_len: 3,
+            })
+        );
+
+        let result = Small::checked_indirect(vec![5], 1, Layout::Aligned, vec![0; 2]);
+        assert_eq!(result.err(), Some(ValidationError::DataLength { expected: 1, found: 2 }));
+
+        let result = Small::checked_indirect(vec![5], 65, Layout::Aligned, vec![0; 64]);
+        assert_eq!(result.err(), Some(ValidationError::BitsPerItemTooLarge(65)));
+
+        let words: Vec<u64> = vec![0b0100, 0, 0];
+        let result = Small::checked_indirect(vec![5, 5], 2, Layout::Spanning, words);
+        assert_eq!(result.err(), Some(ValidationError::DuplicatePaletteEntry(1)));
+    }
+
+    #[test]
+    fn test_corrupt_binary_is_rejected() {
+        let mut section: Small = SectionBuilder::new().bits_per_item(2).build();
+        section.set_item(IVec3::new(0, 0, 1), 3).unwrap();
+        section.set_item(IVec3::new(0, 0, 2), 4).unwrap();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        section.write_to_version(&mut bytes, 1).unwrap();
+
+        // first packed word, after the header and three palette items
+        let word_start: usize = bytes.len() - 8 * 3;
+        bytes[word_start] = 0xff;
+        let result = Small::read_from(&mut bytes.as_slice());
+        assert!(
+            matches!(
+                result,
+                Err(FormatError::Invalid(ValidationError::PaletteIndexOutOfRange { .. }))
+            )
+        );
+
+        // bits per item, after the magic, versions, header length, dimensions and storage
+        bytes[4 + 1 + 1 + 2 + 12 + 1] = 200;
+        let result = Small::read_from(&mut bytes.as_slice());
+        assert!(
+            matches!(result, Err(FormatError::Invalid(ValidationError::BitsPerItemTooLarge(200))))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_raw_section_is_checked() {
+        let raw = |storage: Storage<u64>, min_bits_per_item: u8| RawSection {
+            storage,
+            initial_bits_per_item: 2,
+            compaction_policy: crate::CompactionPolicy::Manual,
+            direct_threshold: None,
+            min_bits_per_item,
+            max_bits_per_item: 8,
+            layout: Layout::Spanning,
+        };
+
+        let section: Small = Small::try_from(raw(Storage::Uniform(3), 0)).unwrap();
+        assert_eq!(section.count_of(&3), 64);
+
+        let result = Small::try_from(raw(Storage::Direct(vec![1; 63]), 0));
+        assert_eq!(result.err(), Some(ValidationError::ItemCount { expected: 64, found: 63 }));
+
+        let result = Small::try_from(raw(Storage::Uniform(3), 9));
+        assert_eq!(result.err(), Some(ValidationError::MinAboveMax { min: 9, max: 8 }));
+
+        let aligned: Storage<u64> = Small::checked_indirect(vec![5], 1, Layout::Aligned, vec![0])
+            .unwrap();
+        let result = Small::try_from(raw(aligned, 0));
+        assert_eq!(
+            result.err(),
+            Some(ValidationError::LayoutMismatch {
+                expected: Layout::Spanning,
+                found: Layout::Aligned,
+            })
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{ PaletteItem, Section, SectionBuilder };
+use glam::IVec3;
+use std::collections::HashMap;
+
+/// Unbounded grid of [`Section`]s addressed with world positions.
+///
+/// Sections are created the first time a non default item is set in them
+/// and dropped again once every item in them is back to the default value.
+///
+/// # Examples
+///
+/// ```
+/// use glam::IVec3;
+/// use chroma::World;
+///
+/// let mut world: World<u64, 16, 16, 16> = World::new();
+/// world.set_item(IVec3::new(-1, 40, 1000), 3);
+///
+/// assert_eq!(*world.item(IVec3::new(-1, 40, 1000)), 3);
+/// assert_eq!(*world.item(IVec3::new(0, 0, 0)), 0);
+/// assert_eq!(world.section_coord(IVec3::new(-1, 40, 1000)), IVec3::new(-1, 2, 62));
+///
+/// world.set_item(IVec3::new(-1, 40, 1000), 0);
+/// assert!(world.is_empty());
+/// ```
+#[derive(Clone)]
+pub struct World<T: PaletteItem + Default, const W: usize, const H: usize, const D: usize> {
+    sections: HashMap<IVec3, Section<T, W, H, D>>,
+    builder: SectionBuilder,
+    default: T,
+}
+
+impl<T: PaletteItem + Default, const W: usize, const H: usize, const D: usize> World<T, W, H, D> {
+    const DIMENSIONS: IVec3 = IVec3::new(W as i32, H as i32, D as i32);
+
+    /// Creates an empty world whose sections are created like [`SectionBuilder::new`].
+    pub fn new() -> Self {
+        Self::with_builder(SectionBuilder::new())
+    }
+
+    /// Creates an empty world whose sections are created by `builder`.
+    pub fn with_builder(builder: SectionBuilder) -> Self {
+        Self {
+            sections: HashMap::new(),
+            builder,
+            default: T::default(),
+        }
+    }
+
+    /// Returns the coordinate of the section holding a world position.
+    #[inline]
+    pub fn section_coord(&self, pos: IVec3) -> IVec3 {
+        pos.div_euclid(Self::DIMENSIONS)
+    }
+
+    /// Returns the position of a world position within its section.
+    #[inline]
+    pub fn local_pos(&self, pos: IVec3) -> IVec3 {
+        pos.rem_euclid(Self::DIMENSIONS)
+    }
+
+    /// Returns the world position of the first item in a section.
+    #[inline]
+    pub fn section_origin(&self, section_coord: IVec3) -> IVec3 {
+        section_coord * Self::DIMENSIONS
+    }
+
+    /// Gets the item at a world position, which is the default value if no section holds it.
+    pub fn item(&self, pos: IVec3) -> &T {
+        match self.sections.get(&self.section_coord(pos)) {
+            Some(section) => {
+                section.item(self.local_pos(pos)).expect("local position is in bounds")
+            }
+            None => &self.default,
+        }
+    }
+
+    /// Sets the item at a world position.
+    ///
+    /// The section holding it is created if needed, and dropped if it is left empty.
+    pub fn set_item(&mut self, pos: IVec3, item: T) {
+        let section_coord: IVec3 = self.section_coord(pos);
+        let local_pos: IVec3 = self.local_pos(pos);
+        let is_default: bool = item == self.default;
+
+        let section: &mut Section<T, W, H, D> = match self.sections.get_mut(&section_coord) {
+            Some(section) => section,
+            None if is_default => return,
+            None => self.sections.entry(section_coord).or_insert_with(|| self.builder.build()),
+        };
+
+        section.set_item(local_pos, item).expect("local position is in bounds");
+
+        if is_default && section.is_empty() {
+            self.sections.remove(&section_coord);
+        }
+    }
+
+    /// Returns the section at a section coordinate, if it exists.
+    #[inline]
+    pub fn section(&self, section_coord: IVec3) -> Option<&Section<T, W, H, D>> {
+        self.sections.get(&section_coord)
+    }
+
+    /// Returns the section at a section coordinate mutably, if it exists.
+    ///
+    /// A section emptied through this reference is kept until [`World::remove_empty`] is called.
+    #[inline]
+    pub fn section_mut(&mut self, section_coord: IVec3) -> Option<&mut Section<T, W, H, D>> {
+        self.sections.get_mut(&section_coord)
+    }
+
+    /// Places a section at a section coordinate, returning the one it replaced.
+    pub fn insert_section(
+        &mut self,
+        section_coord: IVec3,
+        section: Section<T, W, H, D>
+    ) -> Option<Section<T, W, H, D>> {
+        self.sections.insert(section_coord, section)
+    }
+
+    /// Removes and returns the section at a section coordinate.
+    pub fn remove_section(&mut self, section_coord: IVec3) -> Option<Section<T, W, H, D>> {
+        self.sections.remove(&section_coord)
+    }
+
+    /// Drops every section whose items are all the default value.
+    pub fn remove_empty(&mut self) {
+        self.sections.retain(|_, section| !section.is_empty());
+    }
+
+    /// Iterates over every section and its section coordinate, in no particular order.
+    pub fn sections(&self) -> impl Iterator<Item = (IVec3, &Section<T, W, H, D>)> + '_ {
+        self.sections.iter().map(|(&section_coord, section)| (section_coord, section))
+    }
+
+    /// Returns the number of sections held.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.sections.len()
+    }
+
+    /// Returns if no sections are held, so every item is the default value.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+}
+
+impl<T: PaletteItem + Default, const W: usize, const H: usize, const D: usize> Default
+for World<T, W, H, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_are_lazy() {
+        let mut world: World<u64, 4, 4, 4> = World::new();
+        world.set_item(IVec3::new(-5, 0, 3), 0);
+        assert!(world.is_empty());
+
+        world.set_item(IVec3::new(-5, 0, 3), 1);
+        world.set_item(IVec3::new(-6, 1, 2), 2);
+        world.set_item(IVec3::new(4, 4, 4), 3);
+        assert_eq!(world.len(), 2);
+        assert_eq!(*world.item(IVec3::new(-5, 0, 3)), 1);
+        assert_eq!(*world.item(IVec3::new(-6, 1, 2)), 2);
+        assert_eq!(*world.item(IVec3::new(4, 4, 4)), 3);
+
+        let section: &Section<u64, 4, 4, 4> = world.section(IVec3::new(-2, 0, 0)).unwrap();
+        assert_eq!(*section.item(IVec3::new(3, 0, 3)).unwrap(), 1);
+        assert_eq!(world.section_origin(IVec3::new(-2, 0, 0)), IVec3::new(-8, 0, 0));
+
+        world.set_item(IVec3::new(-5, 0, 3), 0);
+        assert_eq!(world.len(), 2);
+        world.set_item(IVec3::new(-6, 1, 2), 0);
+        assert_eq!(world.len(), 1);
+        assert!(world.section(IVec3::new(-2, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_remove_empty() {
+        let mut world: World<u64, 4, 4, 4> = World::with_builder(
+            SectionBuilder::new().bits_per_item(4)
+        );
+        world.set_item(IVec3::new(0, 0, 0), 5);
+        assert_eq!(world.section(IVec3::ZERO).unwrap().bits_per_item(), 4);
+
+        world.section_mut(IVec3::ZERO).unwrap().fill(0);
+        assert_eq!(world.len(), 1);
+        world.remove_empty();
+        assert!(world.is_empty());
+    }
+}